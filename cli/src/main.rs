@@ -1,16 +1,146 @@
 use std::env::{set_var, var};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
 use tracing_subscriber::fmt::layer;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{registry, EnvFilter};
 
-use sim::typedef::{SimulationType, SimulationWriter, SimulationsHandler};
+mod tui;
+
+use sim::rng::random_seed;
+use sim::road::create_road;
+use sim::typedef::{
+    AnimationFormat, BatchConfig, Checkpoint, Comparison, ExperimentConfig, GridAxis,
+    InitialCondition, InitialSpeeds, MetaData, OutputConfig, PointComparison, ProbabilityConfig,
+    ResultFormat, Road, RoadConfig, SimulationsHandler, StateFormat, SweepBranch, SweepConfig,
+    TTest, TrajectoryFormat, VehiclePlacement,
+};
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Without a subcommand a single experiment is run
+    #[clap(flatten)]
+    run: Args,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run every experiment of a batch file, several at the same time
+    Batch(BatchArgs),
+    /// Draw the fundamental diagrams of one or more runs as SVG
+    Plot(PlotArgs),
+    /// Compare the flow, speed and capacity of two runs, point by point
+    Compare(CompareArgs),
+    /// Summarize every results file below a directory in one table
+    Summarize(SummarizeArgs),
+    /// Watch a road in the terminal, pausing, stepping and changing its parameters while it runs
+    Tui(TuiArgs),
+}
+
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// The TOML file with the base experiment, the changes of every run and the output name template
+    file: PathBuf,
+    /// How many experiments run at the same time, overrides the concurrency of the batch file.
+    /// Defaults to the number of available threads.
+    #[clap(long, short)]
+    jobs: Option<usize>,
+}
+
+#[derive(clap::Args)]
+struct PlotArgs {
+    /// The results to draw, `.csv` or `.jsonl`. Every results file in a directory is drawn.
+    #[clap(required = true)]
+    files: Vec<PathBuf>,
+    /// The path of the charts without the extension, they are written to `<output>.flow.svg`,
+    /// `<output>.speed.svg` and `<output>.lane_speed.svg`.
+    /// Defaults to the first results file without its extension, or `<directory>/fundamental_diagram`.
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+    /// The title above the charts
+    #[clap(long)]
+    title: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct CompareArgs {
+    /// The results of the first run, `.csv` or `.jsonl`
+    a: PathBuf,
+    /// The results of the second run, compared to the first
+    b: PathBuf,
+    /// The significance level of the tests
+    #[clap(long, default_value = "0.05")]
+    alpha: f32,
+    /// Also write the comparison of every point to this csv file
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct SummarizeArgs {
+    /// The directory to search for results, `.csv` or `.jsonl`
+    directory: PathBuf,
+    /// The densities at which the average speed is reported, separated by commas
+    #[clap(long, value_delimiter = ',', default_value = "0.1,0.2,0.3")]
+    densities: Vec<f32>,
+    /// The csv file the table is written to, defaults to `<directory>.summary.csv`
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct TuiArgs {
+    #[clap(short)]
+    #[clap(default_value = "100")]
+    road_len: u8,
+    /// The density of the road at the start
+    #[clap(long)]
+    #[clap(default_value = "0.2")]
+    density: f32,
+    #[clap(long)]
+    #[clap(default_value = "0.4")]
+    p_decel: f32,
+    #[clap(long)]
+    #[clap(default_value = "0.8")]
+    p_lane_change: f32,
+    #[clap(long)]
+    #[clap(default_value = "5")]
+    l1: u8,
+    #[clap(long)]
+    #[clap(default_value = "5")]
+    l2: u8,
+    #[clap(long)]
+    #[clap(default_value = "5")]
+    l3: u8,
+    /// Seed of the random numbers, a random seed is chosen when absent
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Start from the road saved in this file instead of a random road.
+    /// The density, lane speeds and road length are taken from the file.
+    #[clap(long)]
+    initial_state: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
 pub struct Args {
+    /// Run the experiment described in a TOML or JSON file.
+    /// All other simulation options are ignored.
+    #[clap(long)]
+    experiment: Option<PathBuf>,
+    /// Continue the interrupted run with this output name, or with this checkpoint file, from its checkpoint.
+    /// The experiment is read from the checkpoint, all other simulation options are ignored.
+    #[clap(long)]
+    resume: Option<String>,
     #[clap(short)]
     #[clap(default_value = "false")]
     verbose: bool,
@@ -27,6 +157,76 @@ pub struct Args {
     #[clap(long, short, value_enum)]
     #[clap(default_value = "density")]
     parameter_under_test: ParameterUnderTest,
+    /// The first value of the parameter under test
+    #[clap(long)]
+    sweep_start: Option<f32>,
+    /// The value of the parameter under test at which the sweep stops.
+    /// Lane speed sweeps include this value.
+    #[clap(long)]
+    sweep_end: Option<f32>,
+    /// The increment of the parameter under test.
+    /// Lane speed and speed differential sweeps take whole numbers and a step of 1.
+    #[clap(long)]
+    sweep_step: Option<f32>,
+    /// The number of densities simulated by an adaptive density sweep
+    #[clap(long)]
+    #[clap(default_value = "50")]
+    point_budget: usize,
+    /// The lane of which the maximum speed is swept, 0 is the rightmost lane
+    #[clap(long)]
+    #[clap(default_value = "0")]
+    sweep_lane: usize,
+    /// The densities of a grid sweep, as a list like `0.1,0.2` or a range like `0.01:0.5:0.01`
+    #[clap(long, value_parser = parse_grid_axis)]
+    grid_density: Option<GridAxis>,
+    /// The deceleration probabilities of a grid sweep, as a list or a range
+    #[clap(long, value_parser = parse_grid_axis)]
+    grid_p_decel: Option<GridAxis>,
+    /// The lane change probabilities of a grid sweep, as a list or a range
+    #[clap(long, value_parser = parse_grid_axis)]
+    grid_p_lane_change: Option<GridAxis>,
+    /// The maximum speeds of the lanes of a grid sweep, separated by commas, like `5-5-5,3-4-5`
+    #[clap(long, value_delimiter = ',', value_parser = parse_lane_speeds)]
+    grid_lane_speeds: Vec<Vec<u8>>,
+    /// The road lengths of a grid sweep, separated by commas
+    #[clap(long, value_delimiter = ',')]
+    grid_road_length: Vec<u8>,
+    /// The number of base samples of a sensitivity analysis
+    #[clap(long)]
+    #[clap(default_value = "64")]
+    sensitivity_samples: usize,
+    /// The lower and upper density of a sensitivity analysis, like `0.05,0.5`
+    #[clap(long, value_parser = parse_bounds::<f32>)]
+    sensitivity_density: Option<[f32; 2]>,
+    /// The lower and upper deceleration probability of a sensitivity analysis
+    #[clap(long, value_parser = parse_bounds::<f32>)]
+    sensitivity_p_decel: Option<[f32; 2]>,
+    /// The lower and upper lane change probability of a sensitivity analysis
+    #[clap(long, value_parser = parse_bounds::<f32>)]
+    sensitivity_p_lane_change: Option<[f32; 2]>,
+    /// The lower and upper maximum speed of every lane in a sensitivity analysis, like `2,5`
+    #[clap(long, value_parser = parse_bounds::<u8>)]
+    sensitivity_lane_speed: Option<[u8; 2]>,
+    /// The csv file with the `density`, `flow` and optionally `speed` columns a calibration is fitted to,
+    /// in vehicles per cell, vehicles per cell and step, and cells per step
+    #[clap(long, required_if_eq("parameter_under_test", "calibration"))]
+    observations: Option<PathBuf>,
+    /// The largest number of parameter sets simulated by a calibration
+    #[clap(long)]
+    #[clap(default_value = "100")]
+    max_evaluations: usize,
+    /// The bounds of the deceleration probability searched by a calibration
+    #[clap(long, value_parser = parse_bounds::<f32>)]
+    #[clap(default_value = "0,1")]
+    calibrate_p_decel: [f32; 2],
+    /// The bounds of the lane change probability searched by a calibration
+    #[clap(long, value_parser = parse_bounds::<f32>)]
+    #[clap(default_value = "0,1")]
+    calibrate_p_lane_change: [f32; 2],
+    /// The bounds of the maximum speed of every lane searched by a calibration
+    #[clap(long, value_parser = parse_bounds::<u8>)]
+    #[clap(default_value = "1,5")]
+    calibrate_lane_speed: [u8; 2],
     #[clap(short)]
     #[clap(default_value = "100")]
     road_len: u8,
@@ -39,18 +239,111 @@ pub struct Args {
     #[clap(long)]
     #[clap(default_value = "5")]
     l3: u8,
+    /// Print the road after every step. The `tui` subcommand shows a single road interactively,
+    /// `--animation` records the steps to a file.
     #[clap(long)]
     #[clap(default_value = "false")]
     pretty_print: bool,
     #[clap(long, short)]
     #[clap(default_value = "")]
     output_name: String,
-    #[clap(short, long)]
+    /// Write the results to this file instead of the data directory, every other output is written next to it
+    #[clap(long)]
+    output_path: Option<PathBuf>,
+    /// The format of the results
+    #[clap(long, value_enum)]
+    #[clap(default_value = "csv")]
+    format: ResultFormatArg,
+    #[clap(long)]
     #[clap(default_value = "0.4")]
     p_decel: f32,
-    #[clap(short, long)]
+    #[clap(long)]
     #[clap(default_value = "0.8")]
     p_lane_change: f32,
+    /// Detect jams during every simulation and write the jam
+    /// statistics to `<output>.jams.csv` and `<output>.jam_distribution.csv`.
+    #[clap(long)]
+    #[clap(default_value = "false")]
+    track_jams: bool,
+    /// Record the travel statistics of every vehicle and
+    /// write them to `<output>.vehicles.csv`.
+    #[clap(long)]
+    #[clap(default_value = "false")]
+    vehicle_statistics: bool,
+    /// Record the trajectory of every vehicle to `<output>.trajectories.csv`
+    /// or, in the compact binary format, to `<output>.trajectories.bin`.
+    #[clap(long, value_enum)]
+    trajectories: Option<TrajectoryFormatArg>,
+    /// The steps of which the trajectories are recorded, as `start..end`.
+    /// Step 0 is the initial state of the road.
+    #[clap(long, value_parser = parse_step_range)]
+    #[clap(default_value = "0..")]
+    trajectory_steps: Range<usize>,
+    /// The ids of the vehicles of which the trajectories are recorded, separated by commas.
+    /// All vehicles are recorded if none are given.
+    #[clap(long, value_delimiter = ',')]
+    trajectory_vehicles: Vec<usize>,
+    /// Record every step of one simulation as an animation in the colours of `--pretty-print`,
+    /// to the asciinema recording `<output>.cast` or to the animated image `<output>.gif`.
+    #[clap(long, value_enum)]
+    animation: Option<AnimationFormatArg>,
+    /// The simulation that is animated, starting at 0
+    #[clap(long)]
+    #[clap(default_value = "0")]
+    animation_simulation: usize,
+    /// The parameter point that is animated, starting at 1
+    #[clap(long)]
+    #[clap(default_value = "1")]
+    animation_iteration: usize,
+    /// How long every step of the animation is shown in milliseconds
+    #[clap(long)]
+    #[clap(default_value = "150")]
+    animation_frame_ms: u64,
+    /// Record histograms of the gaps and time headways between vehicles
+    /// and write them to `<output>.gaps.csv`.
+    #[clap(long)]
+    #[clap(default_value = "false")]
+    gap_histograms: bool,
+    /// Record the number of lane changes in every step and write them to `<output>.lane_changes.csv`.
+    #[clap(long)]
+    #[clap(default_value = "false")]
+    lane_change_steps: bool,
+    /// The number of steps at the start of every simulation
+    /// that are left out of the gap and headway histograms.
+    #[clap(long)]
+    #[clap(default_value = "0")]
+    warmup: usize,
+    /// Where the vehicles are placed on a new road
+    #[clap(long, value_enum)]
+    #[clap(default_value = "random")]
+    placement: PlacementArg,
+    /// The density of every lane, from the rightmost lane to the leftmost lane,
+    /// used with `--placement per-lane`
+    #[clap(long, value_delimiter = ',')]
+    lane_densities: Vec<f32>,
+    /// The speeds of the vehicles on a new road
+    #[clap(long, value_enum)]
+    #[clap(default_value = "random")]
+    initial_speeds: InitialSpeedsArg,
+    /// The relative probability of every speed starting at 0,
+    /// used with `--initial-speeds distribution`
+    #[clap(long, value_delimiter = ',')]
+    speed_weights: Vec<f32>,
+    /// Start every simulation from the road saved in this file instead of a random road.
+    /// The density, lane speeds and road length are taken from the file.
+    #[clap(long)]
+    initial_state: Option<PathBuf>,
+    /// Save the state of the road after this step of every simulation
+    /// to `<output>.state_<simulation>_<iteration>_<step>.json`.
+    #[clap(long)]
+    dump_state_at: Option<usize>,
+    #[clap(long, value_enum)]
+    #[clap(default_value = "json")]
+    state_format: StateFormatArg,
+    /// Seed from which all random numbers are derived.
+    /// A random seed is chosen, and written to the metadata, when absent.
+    #[clap(long)]
+    seed: Option<u64>,
 }
 
 #[derive(ValueEnum, Clone)]
@@ -58,81 +351,707 @@ enum ParameterUnderTest {
     Density,
     PDecel,
     PLaneChange,
+    /// Sweep the density up and back down, carrying the state of the road between densities
+    Hysteresis,
+    /// Sweep the maximum speed of the lane given by `--sweep-lane`
+    LaneSpeed,
+    /// Sweep the difference in maximum speed between neighbouring lanes,
+    /// the leftmost lane keeps the speed given by `--l3`
+    SpeedDifferential,
+    /// Sample the density between `--sweep-start` and `--sweep-end` with `--point-budget` points,
+    /// concentrated where the flow changes fastest
+    AdaptiveDensity,
+    /// Simulate every combination of the values given with the `--grid-*` options
+    Grid,
+    /// Sobol sensitivity analysis of the parameters with bounds given by the `--sensitivity-*` options
+    Sensitivity,
+    /// Search the parameters that best reproduce the fundamental diagram in `--observations`,
+    /// within the bounds given by the `--calibrate-*` options
+    Calibration,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum PlacementArg {
+    /// Every vehicle in a random free cell of a random lane
+    Random,
+    /// The vehicles spread evenly over every lane
+    Uniform,
+    /// The vehicles packed in one block at the start of every lane
+    Megajam,
+    /// Random cells with the densities given by `--lane-densities`
+    PerLane,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum InitialSpeedsArg {
+    /// A random speed between 1 and the maximum speed of the lane
+    Random,
+    Stopped,
+    /// The maximum speed of the lane
+    Max,
+    /// Speeds drawn with the weights given by `--speed-weights`
+    Distribution,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ResultFormatArg {
+    Csv,
+    /// One JSON object per parameter point
+    JsonLines,
+    /// Csv printed to the terminal, every other output is still written to files
+    Stdout,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum StateFormatArg {
+    Json,
+    Binary,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum TrajectoryFormatArg {
+    Csv,
+    Binary,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum AnimationFormatArg {
+    Cast,
+    Gif,
+}
+
+/// Parse a range of steps like `10..20`, `10..` or `..20`
+fn parse_step_range(s: &str) -> std::result::Result<Range<usize>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("Expected a range like 10..20, got '{s}'"))?;
+
+    let parse = |v: &str, default: usize| {
+        if v.is_empty() {
+            Ok(default)
+        } else {
+            v.parse::<usize>()
+                .map_err(|e| format!("Invalid step '{v}': {e}"))
+        }
+    };
+
+    Ok(parse(start, 0)?..parse(end, usize::MAX)?)
+}
+
+/// Parse the values of a grid parameter, either a list like `0.1,0.2,0.4` or a range like `0.1:0.5:0.1`
+fn parse_grid_axis(s: &str) -> std::result::Result<GridAxis, String> {
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<f32>()
+            .map_err(|e| format!("Invalid value '{v}': {e}"))
+    };
+
+    if s.contains(':') {
+        let values = s.split(':').map(parse).collect::<Result<Vec<_>, _>>()?;
+        let [start, end, step] = values[..] else {
+            return Err(format!("Expected a range like 0.1:0.5:0.1, got '{s}'"));
+        };
+        return Ok(GridAxis::Range { start, end, step });
+    }
+
+    Ok(GridAxis::Values(
+        s.split(',').map(parse).collect::<Result<Vec<_>, _>>()?,
+    ))
+}
+
+/// Parse a lower and an upper bound, like `0.1,0.5`
+fn parse_bounds<T>(s: &str) -> std::result::Result<[T; 2], String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let (low, high) = s
+        .split_once(',')
+        .ok_or_else(|| format!("Expected bounds like 0.1,0.5, got '{s}'"))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<T>()
+            .map_err(|e| format!("Invalid bound '{v}': {e}"))
+    };
+
+    Ok([parse(low)?, parse(high)?])
+}
+
+/// Parse the maximum speeds of the three lanes, like `3-4-5`
+fn parse_lane_speeds(s: &str) -> std::result::Result<Vec<u8>, String> {
+    s.split('-')
+        .map(|v| {
+            v.trim()
+                .parse::<u8>()
+                .map_err(|e| format!("Invalid speed '{v}': {e}"))
+        })
+        .collect()
+}
+
+/// A speed given as a sweep bound, which must be a whole number of cells per step
+fn sweep_speed(name: &str, value: f32) -> Result<u8> {
+    if value.fract() != 0.0 || !(0.0..=u8::MAX as f32).contains(&value) {
+        return Err(eyre!(
+            "{name} must be a whole number between 0 and {} for this sweep, got {value}",
+            u8::MAX
+        ));
+    }
+    Ok(value as u8)
+}
+
+/// Describe the experiment given by the command line arguments
+fn experiment_from_args(args: &Args) -> Result<ExperimentConfig> {
+    let (start, end, step) = match args.parameter_under_test {
+        ParameterUnderTest::Density
+        | ParameterUnderTest::Hysteresis
+        | ParameterUnderTest::AdaptiveDensity => (0.01, 0.5, 0.003333333),
+        ParameterUnderTest::PDecel | ParameterUnderTest::PLaneChange => (0.01, 1.0, 0.001),
+        ParameterUnderTest::LaneSpeed => (1.0, 5.0, 1.0),
+        ParameterUnderTest::SpeedDifferential => (0.0, 2.0, 1.0),
+        ParameterUnderTest::Grid
+        | ParameterUnderTest::Sensitivity
+        | ParameterUnderTest::Calibration => (0.0, 0.0, 0.0),
+    };
+    let start = args.sweep_start.unwrap_or(start);
+    let end = args.sweep_end.unwrap_or(end);
+    let step = args.sweep_step.unwrap_or(step);
+
+    let sweeps_speed = matches!(
+        args.parameter_under_test,
+        ParameterUnderTest::LaneSpeed | ParameterUnderTest::SpeedDifferential
+    );
+    if sweeps_speed && step != 1.0 {
+        return Err(eyre!(
+            "--sweep-step must be 1 for speed sweeps, which try every speed, got {step}"
+        ));
+    }
+
+    let sweep = match args.parameter_under_test {
+        ParameterUnderTest::Density => SweepConfig::Density { start, end, step },
+        ParameterUnderTest::PDecel => SweepConfig::PDecel { start, end, step },
+        ParameterUnderTest::PLaneChange => SweepConfig::PLaneChange { start, end, step },
+        ParameterUnderTest::Hysteresis => SweepConfig::Hysteresis { start, end, step },
+        ParameterUnderTest::AdaptiveDensity => SweepConfig::AdaptiveDensity {
+            start,
+            end,
+            budget: args.point_budget,
+        },
+        ParameterUnderTest::LaneSpeed => SweepConfig::LaneSpeed {
+            lane: args.sweep_lane,
+            start: sweep_speed("--sweep-start", start)?,
+            end: sweep_speed("--sweep-end", end)?,
+        },
+        ParameterUnderTest::SpeedDifferential => SweepConfig::SpeedDifferential {
+            start: sweep_speed("--sweep-start", start)?,
+            end: sweep_speed("--sweep-end", end)?,
+        },
+        ParameterUnderTest::Grid => SweepConfig::Grid {
+            density: args.grid_density.clone(),
+            p_decel: args.grid_p_decel.clone(),
+            p_lane_change: args.grid_p_lane_change.clone(),
+            lane_speeds: (!args.grid_lane_speeds.is_empty()).then(|| args.grid_lane_speeds.clone()),
+            road_length: (!args.grid_road_length.is_empty()).then(|| args.grid_road_length.clone()),
+        },
+        ParameterUnderTest::Sensitivity => SweepConfig::Sensitivity {
+            samples: args.sensitivity_samples,
+            density: args.sensitivity_density,
+            p_decel: args.sensitivity_p_decel,
+            p_lane_change: args.sensitivity_p_lane_change,
+            lane_speed: args.sensitivity_lane_speed,
+        },
+        ParameterUnderTest::Calibration => SweepConfig::Calibration {
+            observations: args.observations.clone().unwrap_or_default(),
+            max_evaluations: args.max_evaluations,
+            p_decel: args.calibrate_p_decel,
+            p_lane_change: args.calibrate_p_lane_change,
+            lane_speed: args.calibrate_lane_speed,
+        },
+    };
+
+    Ok(ExperimentConfig {
+        repetitions: args.simulations,
+        iterations: args.iterations,
+        seed: args.seed,
+        road: RoadConfig {
+            length: args.road_len,
+            lane_speeds: vec![args.l1, args.l2, args.l3],
+            initial_condition: InitialCondition {
+                placement: match args.placement {
+                    PlacementArg::Random => VehiclePlacement::Random,
+                    PlacementArg::Uniform => VehiclePlacement::Uniform,
+                    PlacementArg::Megajam => VehiclePlacement::Megajam,
+                    PlacementArg::PerLane => VehiclePlacement::PerLane {
+                        densities: args.lane_densities.clone(),
+                    },
+                },
+                speeds: match args.initial_speeds {
+                    InitialSpeedsArg::Random => InitialSpeeds::Random,
+                    InitialSpeedsArg::Stopped => InitialSpeeds::Stopped,
+                    InitialSpeedsArg::Max => InitialSpeeds::Max,
+                    InitialSpeedsArg::Distribution => InitialSpeeds::Distribution {
+                        weights: args.speed_weights.clone(),
+                    },
+                },
+            },
+            initial_state: args.initial_state.clone(),
+            ..Default::default()
+        },
+        probabilities: ProbabilityConfig {
+            deceleration: args.p_decel,
+            lane_change: args.p_lane_change,
+        },
+        sweep,
+        output: OutputConfig {
+            name: (!args.output_name.is_empty()).then(|| args.output_name.clone()),
+            path: args.output_path.clone(),
+            format: match args.format {
+                ResultFormatArg::Csv => ResultFormat::Csv,
+                ResultFormatArg::JsonLines => ResultFormat::JsonLines,
+                ResultFormatArg::Stdout => ResultFormat::Stdout,
+            },
+            track_jams: args.track_jams,
+            vehicle_statistics: args.vehicle_statistics,
+            gap_histograms: args.gap_histograms,
+            lane_change_steps: args.lane_change_steps,
+            warmup: args.warmup,
+            trajectories: args.trajectories.map(|format| match format {
+                TrajectoryFormatArg::Csv => TrajectoryFormat::Csv,
+                TrajectoryFormatArg::Binary => TrajectoryFormat::Binary,
+            }),
+            trajectory_start: args.trajectory_steps.start,
+            trajectory_end: (args.trajectory_steps.end != usize::MAX)
+                .then_some(args.trajectory_steps.end),
+            trajectory_vehicles: args.trajectory_vehicles.clone(),
+            animation: args.animation.map(|format| match format {
+                AnimationFormatArg::Cast => AnimationFormat::Cast,
+                AnimationFormatArg::Gif => AnimationFormat::Gif,
+            }),
+            animation_simulation: args.animation_simulation,
+            animation_iteration: Some(args.animation_iteration),
+            animation_frame_ms: Some(args.animation_frame_ms),
+            dump_state_at: args.dump_state_at,
+            state_format: match args.state_format {
+                StateFormatArg::Json => StateFormat::Json,
+                StateFormatArg::Binary => StateFormat::Binary,
+            },
+        },
+    })
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
-    let args = Args::parse();
+    let cli = Cli::parse();
 
     if var("RUST_LOG").is_err() {
-        if args.verbose {
+        if cli.run.verbose {
             set_var("RUST_LOG", "TRACE");
         } else {
             set_var("RUST_LOG", "INFO");
         }
     }
 
-    println!("Path: {}", args.output_name);
-
     registry()
         .with(layer().compact())
         .with(EnvFilter::from_default_env())
         .init();
 
-    let now = chrono::Utc::now();
-    let fmt = now.format("%Y-%m-%d_%H%M").to_string();
+    match cli.command {
+        Some(Command::Batch(batch)) => run_batch(&batch),
+        Some(Command::Plot(plot)) => run_plot(&plot),
+        Some(Command::Compare(compare)) => run_compare(&compare),
+        Some(Command::Summarize(summarize)) => run_summarize(&summarize),
+        Some(Command::Tui(tui)) => run_tui(&tui),
+        None => run(&cli.run),
+    }
+}
+
+/// Run every experiment of a batch file and print how each of them went
+fn run_batch(args: &BatchArgs) -> Result<()> {
+    let config = BatchConfig::from_file(&args.file)?;
+    let experiments = config.experiments()?;
+    let total = experiments.len();
+
+    // Check all output files up front, so a batch does not stop halfway because of a name clash
+    for experiment in &experiments {
+        let file_path = experiment.output_file_path();
+        if file_path.exists() {
+            return Err(eyre!(
+                "Output file {} already exists. Please choose a different name.",
+                file_path.display()
+            ));
+        }
+    }
+
+    let concurrency = args.jobs.or(config.concurrency).unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    println!("Running {total} experiments, {concurrency} at a time");
+
+    // The first Ctrl-C lets every running experiment finish its current parameter point and save
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!(
+            "Interrupted, saving the running experiments. Press Ctrl-C again to quit immediately."
+        );
+    })?;
+
+    let start = std::time::Instant::now();
+    let summaries = sim::batch::run_batch(experiments, concurrency, interrupted, |summary| {
+        println!(
+            "{} {} ({} points in {:.1?})",
+            if summary.error.is_some() {
+                "Failed"
+            } else if summary.interrupted {
+                "Interrupted"
+            } else {
+                "Finished"
+            },
+            summary.name,
+            summary.points,
+            summary.duration
+        );
+    });
+
+    let name_width = summaries
+        .iter()
+        .map(|s| s.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Experiment".len());
+    println!();
+    println!(
+        "{:<name_width$}  {:>6}  {:>10}  Status",
+        "Experiment", "Points", "Time"
+    );
+    for summary in &summaries {
+        let status = match (&summary.error, summary.interrupted) {
+            (Some(error), _) => format!("failed: {error}"),
+            (None, true) => format!("interrupted, continue with --resume {}", summary.name),
+            (None, false) => "finished".to_string(),
+        };
+        println!(
+            "{:<name_width$}  {:>6}  {:>10}  {status}",
+            summary.name,
+            summary.points,
+            format!("{:.1?}", summary.duration)
+        );
+    }
+
+    let failed = summaries.iter().filter(|s| s.error.is_some()).count();
+    let finished = summaries
+        .iter()
+        .filter(|s| s.error.is_none() && !s.interrupted)
+        .count();
+    println!(
+        "{finished} of {total} experiments finished, {failed} failed, {} not started, in {:.1?}",
+        total - summaries.len(),
+        start.elapsed()
+    );
+
+    if failed > 0 {
+        return Err(eyre!("{failed} experiments of the batch failed"));
+    }
+    Ok(())
+}
+
+/// Draw the fundamental diagrams of the given results
+fn run_plot(args: &PlotArgs) -> Result<()> {
+    let mut files = Vec::new();
+    for path in &args.files {
+        if path.is_dir() {
+            files.extend(sim::loader::find_results(path)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    if files.is_empty() {
+        return Err(eyre!("No results found"));
+    }
+
+    let results = files
+        .iter()
+        .map(|file| sim::loader::load_results(file))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let output = args.output.clone().unwrap_or_else(|| {
+        let first = &args.files[0];
+        if first.is_dir() {
+            first.join("fundamental_diagram")
+        } else {
+            first.with_extension("")
+        }
+    });
+
+    let charts = sim::plot::plot_fundamental_diagrams(&results, &output, args.title.as_deref())?;
+    println!("Drew {} runs", results.len());
+    for chart in charts {
+        println!("{}", chart.display());
+    }
+    Ok(())
+}
 
-    let sim_type = match args.parameter_under_test {
-        ParameterUnderTest::Density => SimulationType::Density(0.01, 0.5, 0.003333333),
-        ParameterUnderTest::PDecel => SimulationType::Deceleration(0.01, 1.0, 0.001),
-        ParameterUnderTest::PLaneChange => SimulationType::LaneChange(0.01, 1.0, 0.001),
+/// Compare two runs and print the differences and their significance
+fn run_compare(args: &CompareArgs) -> Result<()> {
+    let a = sim::loader::load_results(&args.a)?;
+    let b = sim::loader::load_results(&args.b)?;
+    let comparison = Comparison::new(&a.iteration_infos, &b.iteration_infos);
+
+    println!("a: {}", args.a.display());
+    println!("b: {}", args.b.display());
+    if comparison.points.is_empty() {
+        return Err(eyre!("The runs have no parameter point in common"));
+    }
+
+    let p_value = |test: Option<TTest>| match test {
+        Some(test) if test.p_value < args.alpha => format!("{:.4}*", test.p_value),
+        Some(test) => format!("{:.4} ", test.p_value),
+        None => "-".to_string(),
     };
+    println!();
+    println!(
+        "{:>8}  {:>8}  {:>8}  {:>9}  {:>8}  {:>8}  {:>8}  {:>9}  {:>8}",
+        "Density", "Flow a", "Flow b", "Diff", "p", "Speed a", "Speed b", "Diff", "p"
+    );
+    for point in &comparison.points {
+        let mut density = format!("{:.4}", point.density);
+        if let Some(branch) = point.branch {
+            density = format!(
+                "{density} {}",
+                if branch == SweepBranch::Up {
+                    "up"
+                } else {
+                    "down"
+                }
+            );
+        }
+        println!(
+            "{:>8}  {:>8.4}  {:>8.4}  {:>+9.4}  {:>8}  {:>8.4}  {:>8.4}  {:>+9.4}  {:>8}",
+            density,
+            point.flow.a,
+            point.flow.b,
+            point.flow.difference,
+            p_value(point.flow.test),
+            point.average_speed.a,
+            point.average_speed.b,
+            point.average_speed.difference,
+            p_value(point.average_speed.test),
+        );
+    }
+    println!();
 
-    let file_name = if args.output_name != "" {
-        let output_name = args.output_name;
-        format!("{output_name}.csv")
+    let (unmatched_a, unmatched_b) = comparison.unmatched;
+    println!(
+        "{} points in common, {unmatched_a} only in a, {unmatched_b} only in b",
+        comparison.points.len()
+    );
+    if comparison.points.iter().all(|p| p.flow.test.is_none()) {
+        println!("The spread over the simulations was not written, so the points are not tested one by one");
     } else {
-        match args.parameter_under_test {
-            ParameterUnderTest::Density => format!("density_{fmt}.csv"),
-            ParameterUnderTest::PDecel => format!("p_decel_{fmt}.csv"),
-            ParameterUnderTest::PLaneChange => format!("p_lane_change_{fmt}.csv"),
+        println!(
+            "Significant at {}: flow at {} points, average speed at {} points",
+            args.alpha,
+            comparison.significant_points(args.alpha, |p| p.flow),
+            comparison.significant_points(args.alpha, |p| p.average_speed)
+        );
+    }
+    let mean_difference = |difference: fn(&PointComparison) -> f32| {
+        comparison.points.iter().map(difference).sum::<f32>() / comparison.points.len() as f32
+    };
+    println!(
+        "Mean flow difference {:+.4}, paired t-test p = {}",
+        mean_difference(|p| p.flow.difference),
+        p_value(comparison.flow_test)
+    );
+    println!(
+        "Mean average speed difference {:+.4}, paired t-test p = {}",
+        mean_difference(|p| p.average_speed.difference),
+        p_value(comparison.average_speed_test)
+    );
+
+    if let (Some(capacity_a), Some(capacity_b)) = (comparison.capacity_a, comparison.capacity_b) {
+        println!(
+            "Capacity a: flow {:.4} at density {:.4}",
+            capacity_a.flow, capacity_a.density
+        );
+        println!(
+            "Capacity b: flow {:.4} at density {:.4}",
+            capacity_b.flow, capacity_b.density
+        );
+        println!(
+            "Capacity difference: flow {:+.4}, density {:+.4}, p = {}",
+            capacity_b.flow - capacity_a.flow,
+            capacity_b.density - capacity_a.density,
+            p_value(comparison.capacity_test)
+        );
+    }
+
+    if let Some(output) = &args.output {
+        comparison.write_csv(output)?;
+        println!("Comparison written to {}", output.display());
+    }
+    Ok(())
+}
+
+/// Write the summary table of every results file below a directory
+fn run_summarize(args: &SummarizeArgs) -> Result<()> {
+    let summaries = sim::summary::summarize_directory(&args.directory, &args.densities)?;
+    if summaries.is_empty() {
+        return Err(eyre!("No results found in {}", args.directory.display()));
+    }
+
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => {
+            let directory = args.directory.canonicalize()?;
+            let name = directory.file_name().unwrap_or_default().to_string_lossy();
+            directory.with_file_name(format!("{name}.summary.csv"))
+        }
+    };
+    sim::summary::write_summary_table(&summaries, &output)?;
+    println!(
+        "Summarized {} results to {}",
+        summaries.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Show a road in the terminal interface
+fn run_tui(args: &TuiArgs) -> Result<()> {
+    ProbabilityConfig {
+        deceleration: args.p_decel,
+        lane_change: args.p_lane_change,
+    }
+    .validate()?;
+
+    let road = match &args.initial_state {
+        Some(file_path) => Road::load_state(file_path)?,
+        None => {
+            let road_config = RoadConfig {
+                length: args.road_len,
+                lane_speeds: vec![args.l1, args.l2, args.l3],
+                standard_density: args.density,
+                ..RoadConfig::default()
+            };
+            road_config.validate()?;
+            road_config.validate_density("--density", args.density, args.road_len)?;
+
+            create_road(
+                args.road_len as usize,
+                args.density,
+                road_config.lane_speeds,
+                args.p_decel,
+                args.p_lane_change,
+                &InitialCondition::default(),
+                args.seed.unwrap_or_else(random_seed),
+            )
+        }
+    };
+    tui::run(road, args.p_lane_change)
+}
+
+/// Run a single experiment, described by the command line, a file or a checkpoint
+fn run(args: &Args) -> Result<()> {
+    let experiment = match (&args.resume, &args.experiment) {
+        (Some(name), _) => {
+            // A run with its own output path is resumed from the path of its checkpoint
+            let checkpoint_path = if name.ends_with(".checkpoint") {
+                PathBuf::from(name)
+            } else {
+                PathBuf::from("data").join(format!("{name}.checkpoint"))
+            };
+            Checkpoint::read_experiment(&checkpoint_path)?
         }
+        (None, Some(path)) => ExperimentConfig::from_file(path)?,
+        (None, None) => experiment_from_args(args)?,
     };
 
+    let parameter_name = experiment.sweep.parameter_name();
+    let experiment = experiment.resolve(|| {
+        let now = chrono::Utc::now();
+        let fmt = now.format("%Y-%m-%d_%H%M").to_string();
+
+        format!("{parameter_name}_{fmt}")
+    });
+
+    let file_path = experiment.output_file_path();
+    if experiment.output.format != ResultFormat::Stdout {
+        println!("Path: {}", file_path.display());
+    }
+
     //Check if the output file already exists or if the path is invalid
-    if std::path::Path::new(&file_name).exists() {
+    if args.resume.is_none() && file_path.exists() {
         eprintln!("Output file already exists. Please choose a different name.");
         std::process::exit(1);
     }
 
-    let simulation_handler = SimulationsHandler::new(
-        args.simulations,
-        args.iterations,
-        args.p_decel,
-        args.p_lane_change,
-        sim_type.clone(),
-        SimulationWriter::new(&file_name),
+    let simulation_handler = SimulationsHandler::from_experiment(
+        &experiment,
         args.verbose,
-        vec![args.l1, args.l2, args.l3],
         args.pretty_print,
-    );
+        args.resume.is_some(),
+    )?;
+
+    // The first Ctrl-C finishes the current parameter point and saves the partial results
+    let interrupted = simulation_handler.interrupt_flag();
+    ctrlc::set_handler(move || {
+        if interrupted.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted, saving the completed parameter points. Press Ctrl-C again to quit immediately.");
+    })?;
 
     // Construct the MetaData
-    let metadata = sim::typedef::MetaData {
-        road_len: args.road_len,
-        num_simulations: args.simulations,
-        iterations_per_simulation: args.iterations,
-        sim_type,
-        speeds_per_lane: vec![args.l1, args.l2, args.l3],
-    };
+    let mut metadata = MetaData::from_experiment(&experiment);
 
     let start = std::time::Instant::now();
 
     let iteration_infos = simulation_handler.run_simulations();
 
     let duration = start.elapsed();
+    metadata.wall_time = Some(duration.as_secs_f64());
+    metadata.interrupted = simulation_handler.was_interrupted();
+
+    simulation_handler.save_simulation_results(&iteration_infos, &metadata)?;
 
-    simulation_handler.save_simulation_results(&iteration_infos, &metadata);
+    if let Some(result) = simulation_handler.calibration_result() {
+        let best = &result.best;
+        println!(
+            "Calibrated after {} parameter sets: p_decel {}, p_lane_change {}, lane speeds {:?}, flow RMSE {}, speed RMSE {}, error {}",
+            result.evaluations,
+            best.deceleration_probability,
+            best.lane_change_probability,
+            best.lane_speeds,
+            best.flow_rmse,
+            best.speed_rmse
+                .map_or_else(|| "-".to_string(), |rmse| rmse.to_string()),
+            best.error
+        );
+    }
+
+    if simulation_handler.was_interrupted() {
+        let resume = match experiment.output.path {
+            Some(_) => simulation_handler
+                .simulation_writer
+                .sibling_file_path("checkpoint")
+                .display()
+                .to_string(),
+            None => experiment.output.name.clone().unwrap_or_default(),
+        };
+        eprintln!("Saved partial results, continue the run with --resume {resume}");
+    } else {
+        simulation_handler.remove_checkpoint()?;
+    }
+
+    if let Some(e) = simulation_handler.take_output_error() {
+        return Err(e.into());
+    }
 
     if args.verbose {
         println!("Simulation took {:?}", duration);
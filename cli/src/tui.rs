@@ -0,0 +1,412 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::symbols;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use sim::simulation_writer::nan_to_zero;
+use sim::typedef::{Road, Velocity};
+
+/// The number of steps shown in the live plots
+const HISTORY_LENGTH: usize = 500;
+/// The slowest and fastest time between two steps
+const DELAY_RANGE: (Duration, Duration) = (Duration::from_millis(10), Duration::from_millis(2000));
+/// How much a key press changes a probability
+const PROBABILITY_STEP: f32 = 0.05;
+/// Lane speeds are drawn as a single digit
+const MAX_LANE_SPEED: u8 = 9;
+/// The width of the lane name and speed in front of every lane
+const LANE_LABEL_WIDTH: u16 = 6;
+
+const KEYS: &str = "space pause  n step  +/- speed  d/D p_decel  c/C p_lane_change  1-3 lane  \u{2191}/\u{2193} lane speed  a/x add/remove vehicle  \u{2190}/\u{2192} scroll  q quit";
+
+/// The colors of the vehicles by the lane they started in, as in `Road::pretty_print`
+const LANE_COLORS: [Color; 3] = [Color::Blue, Color::Green, Color::Red];
+
+/// The state of the terminal interface
+struct App {
+    road: Road,
+    lane_change_probability: f32,
+    paused: bool,
+    delay: Duration,
+    /// The lane whose maximum speed is changed with the arrow keys
+    selected_lane: usize,
+    /// The first cell of the road that is drawn
+    scroll: usize,
+    /// The number of cells drawn in the last frame
+    visible_cells: usize,
+    /// Per step: the step, the flow, the average speed and the average speed of every lane
+    history: VecDeque<(f64, f64, f64, [f64; 3])>,
+    quit: bool,
+}
+
+/// Show the road in the terminal and step it, until the user quits.
+/// The simulation can be paused, stepped and sped up, and its parameters changed while it runs.
+/// # Arguments
+/// * `road` - The road to start from
+/// * `lane_change_probability` - The lane change probability of the vehicles
+pub fn run(road: Road, lane_change_probability: f32) -> Result<()> {
+    let mut app = App {
+        road,
+        lane_change_probability,
+        paused: false,
+        delay: Duration::from_millis(150),
+        selected_lane: 0,
+        scroll: 0,
+        visible_cells: 0,
+        history: VecDeque::with_capacity(HISTORY_LENGTH),
+        quit: false,
+    };
+    app.record();
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut next_step = Instant::now() + self.delay;
+
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let timeout = if self.paused {
+                Duration::from_millis(250)
+            } else {
+                next_step.saturating_duration_since(Instant::now())
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key);
+                    }
+                }
+            }
+
+            if !self.paused && Instant::now() >= next_step {
+                self.step();
+                next_step = Instant::now() + self.delay;
+            }
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) {
+        self.road.update_vehicles();
+        self.record();
+    }
+
+    /// Add the measurements of the current step to the live plots
+    fn record(&mut self) {
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        let lane_speeds = self.road.get_average_speed_per_lane();
+        self.history.push_back((
+            self.road.steps as f64,
+            self.road.get_flow() as f64,
+            self.road.get_average_speed() as f64,
+            [0, 1, 2].map(|lane| lane_speeds[lane] as f64),
+        ));
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let lane_count = self.road.speed_per_lane.len();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Char('n') | KeyCode::Char('.') if self.paused => self.step(),
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.delay = (self.delay / 2).max(DELAY_RANGE.0);
+            }
+            KeyCode::Char('-') => self.delay = (self.delay * 2).min(DELAY_RANGE.1),
+            KeyCode::Char('d') => self.change_deceleration_probability(-PROBABILITY_STEP),
+            KeyCode::Char('D') => self.change_deceleration_probability(PROBABILITY_STEP),
+            KeyCode::Char('c') => self.change_lane_change_probability(-PROBABILITY_STEP),
+            KeyCode::Char('C') => self.change_lane_change_probability(PROBABILITY_STEP),
+            KeyCode::Char(c @ '1'..='9') => {
+                let lane = c as usize - '1' as usize;
+                if lane < lane_count {
+                    self.selected_lane = lane;
+                }
+            }
+            KeyCode::Up => self.change_lane_speed(1),
+            KeyCode::Down => self.change_lane_speed(-1),
+            KeyCode::Char('a') => self.change_vehicle_count(1),
+            KeyCode::Char('A') => self.change_vehicle_count(10),
+            KeyCode::Char('x') => self.change_vehicle_count(-1),
+            KeyCode::Char('X') => self.change_vehicle_count(-10),
+            KeyCode::Left => self.scroll_by(-1),
+            KeyCode::Right => self.scroll_by(1),
+            KeyCode::PageUp => self.scroll_by(-(self.visible_cells.max(1) as isize)),
+            KeyCode::PageDown => self.scroll_by(self.visible_cells.max(1) as isize),
+            KeyCode::Home => self.scroll = 0,
+            _ => {}
+        }
+    }
+
+    fn change_deceleration_probability(&mut self, change: f32) {
+        self.road.deceleration_probability =
+            (self.road.deceleration_probability + change).clamp(0.0, 1.0);
+    }
+
+    fn change_lane_change_probability(&mut self, change: f32) {
+        self.lane_change_probability = (self.lane_change_probability + change).clamp(0.0, 1.0);
+        self.road
+            .set_lane_change_probability(self.lane_change_probability);
+    }
+
+    fn change_lane_speed(&mut self, change: i8) {
+        let lane = self.selected_lane as u8;
+        if let Some(speed) = self.road.get_max_velocity_in_lane(lane) {
+            let speed = speed.into_inner().saturating_add_signed(change);
+            self.road
+                .set_max_velocity_in_lane(lane, Velocity::new(speed.clamp(1, MAX_LANE_SPEED)));
+        }
+    }
+
+    fn change_vehicle_count(&mut self, change: isize) {
+        let count = self.road.vehicles.len().saturating_add_signed(change);
+        self.road
+            .set_vehicle_count(count, self.lane_change_probability);
+    }
+
+    /// Move the drawn part of the road, the road is a ring so scrolling wraps around
+    fn scroll_by(&mut self, cells: isize) {
+        let len = self.road.len as isize;
+        self.scroll = (self.scroll as isize + cells).rem_euclid(len) as usize;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let lanes = self.road.speed_per_lane.len() as u16;
+        let [road_area, status_area, charts_area, keys_area] = Layout::vertical([
+            // Both edges of the road, the lanes and the markings between them, inside a border
+            Constraint::Length(2 * lanes + 3),
+            Constraint::Length(6),
+            Constraint::Min(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_road(frame, road_area);
+        self.draw_status(frame, status_area);
+
+        let [flow_area, speed_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(charts_area);
+        self.draw_flow_chart(frame, flow_area);
+        self.draw_speed_chart(frame, speed_area);
+
+        frame.render_widget(Paragraph::new(KEYS).dark_gray(), keys_area);
+    }
+
+    fn draw_road(&mut self, frame: &mut Frame, area: Rect) {
+        let len = self.road.len as usize;
+        let lanes = self.road.speed_per_lane.len();
+        self.visible_cells = (area.width.saturating_sub(2 + LANE_LABEL_WIDTH) as usize).min(len);
+        if self.visible_cells == len {
+            self.scroll = 0;
+        }
+        let cells = (0..self.visible_cells)
+            .map(|i| (self.scroll + i) % len)
+            .collect::<Vec<_>>();
+
+        let mut grid = vec![vec![None; len]; lanes];
+        for vehicle in &self.road.vehicles {
+            grid[vehicle.position.y as usize][vehicle.position.x as usize] = Some(vehicle);
+        }
+
+        let edge = Line::from(vec![
+            Span::raw(" ".repeat(LANE_LABEL_WIDTH as usize)),
+            Span::raw("\u{2501}".repeat(cells.len())),
+        ]);
+        let marking = Line::from(vec![
+            Span::raw(" ".repeat(LANE_LABEL_WIDTH as usize)),
+            Span::raw(
+                cells
+                    .iter()
+                    .map(|x| if x % 4 == 0 { '-' } else { ' ' })
+                    .collect::<String>(),
+            )
+            .dark_gray(),
+        ]);
+
+        let mut lines = vec![edge.clone()];
+        // The leftmost lane is drawn on top, like in `Road::pretty_print`
+        for lane in (0..lanes).rev() {
+            let mut label = Span::raw(format!(
+                "L{} v{} ",
+                lane + 1,
+                self.road.speed_per_lane[lane].into_inner()
+            ));
+            if lane == self.selected_lane {
+                label = label.add_modifier(Modifier::REVERSED);
+            }
+
+            let mut spans = vec![label];
+            spans.extend(cells.iter().map(|&x| {
+                match grid[lane][x] {
+                    Some(vehicle) => Span::styled(
+                        vehicle.velocity.into_inner().to_string(),
+                        Style::default()
+                            .fg(LANE_COLORS[vehicle.original_lane as usize % LANE_COLORS.len()])
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    None => Span::raw(" "),
+                }
+            }));
+            lines.push(Line::from(spans));
+            if lane > 0 {
+                lines.push(marking.clone());
+            }
+        }
+        lines.push(edge);
+
+        let title = if self.visible_cells < len {
+            format!(
+                " Road, cells {} to {} of {} ",
+                self.scroll,
+                (self.scroll + self.visible_cells - 1) % len,
+                len
+            )
+        } else {
+            format!(" Road, {len} cells ")
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let road = &self.road;
+        let state = if self.paused {
+            "Paused".yellow()
+        } else {
+            "Running".green()
+        };
+        let lane_speeds = road
+            .get_average_speed_per_lane()
+            .iter()
+            .map(|speed| format!("{:.2}", if speed.is_nan() { 0.0 } else { *speed }))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        let lines = vec![
+            Line::from(vec![
+                state,
+                Span::raw(format!(
+                    "  step {}  {} ms per step",
+                    road.steps,
+                    self.delay.as_millis()
+                )),
+            ]),
+            Line::from(format!(
+                "Vehicles {}  density {:.3}  flow {:.3}  average speed {:.2}",
+                road.vehicles.len(),
+                road.get_density(),
+                nan_to_zero(road.get_flow()),
+                nan_to_zero(road.get_average_speed())
+            )),
+            Line::from(format!(
+                "p_decel {:.2}  p_lane_change {:.2}",
+                road.deceleration_probability, self.lane_change_probability
+            )),
+            Line::from(format!("Average speed per lane  {lane_speeds}")),
+        ];
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Parameters ")),
+            area,
+        );
+    }
+
+    fn draw_flow_chart(&self, frame: &mut Frame, area: Rect) {
+        let flow = self
+            .history
+            .iter()
+            .filter(|(_, flow, _, _)| flow.is_finite())
+            .map(|(step, flow, _, _)| (*step, *flow))
+            .collect::<Vec<_>>();
+        let max = flow.iter().map(|(_, y)| *y).fold(0.1, f64::max) * 1.1;
+
+        let datasets = vec![line_dataset("flow", Color::Cyan, &flow)];
+        frame.render_widget(self.chart(datasets, " Flow ", max), area);
+    }
+
+    fn draw_speed_chart(&self, frame: &mut Frame, area: Rect) {
+        let speed = self
+            .history
+            .iter()
+            .filter(|(_, _, speed, _)| speed.is_finite())
+            .map(|(step, _, speed, _)| (*step, *speed))
+            .collect::<Vec<_>>();
+        let lane_speeds = (0..3)
+            .map(|lane| {
+                self.history
+                    .iter()
+                    .filter(|(_, _, _, lanes)| lanes[lane].is_finite())
+                    .map(|(step, _, _, lanes)| (*step, lanes[lane]))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let max = self
+            .road
+            .speed_per_lane
+            .iter()
+            .map(|speed| speed.into_inner())
+            .max()
+            .unwrap_or(5) as f64;
+
+        let lane_names = ["lane 1", "lane 2", "lane 3"];
+        let mut datasets = lane_speeds
+            .iter()
+            .enumerate()
+            .map(|(lane, points)| line_dataset(lane_names[lane], LANE_COLORS[lane], points))
+            .collect::<Vec<_>>();
+        datasets.push(line_dataset("all", Color::White, &speed));
+        frame.render_widget(self.chart(datasets, " Average speed ", max), area);
+    }
+
+    /// A chart of the recorded steps, from 0 up to `max`
+    fn chart<'a>(&self, datasets: Vec<Dataset<'a>>, title: &'a str, max: f64) -> Chart<'a> {
+        let first = self.history.front().map_or(0.0, |(step, ..)| *step);
+        let last = self
+            .history
+            .back()
+            .map_or(1.0, |(step, ..)| *step)
+            .max(first + 1.0);
+
+        Chart::new(datasets)
+            .block(Block::bordered().title(title))
+            .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)))
+            .x_axis(
+                Axis::default()
+                    .title("step")
+                    .bounds([first, last])
+                    .labels([format!("{first}"), format!("{last}")]),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, max])
+                    .labels(["0".to_string(), format!("{max:.2}")]),
+            )
+    }
+}
+
+fn line_dataset<'a>(name: &'a str, color: Color, points: &'a [(f64, f64)]) -> Dataset<'a> {
+    Dataset::default()
+        .name(name)
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(color))
+        .data(points)
+}
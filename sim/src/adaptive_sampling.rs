@@ -0,0 +1,172 @@
+use crate::typedef::{IterationInfo, ParameterPoint, SimulationsHandler};
+
+/// The fraction of the point budget spent on the initial uniform grid
+const INITIAL_FRACTION: usize = 4;
+/// The smallest number of points in the initial uniform grid
+const MIN_INITIAL_POINTS: usize = 3;
+
+impl SimulationsHandler {
+    /// Sample the density between start and end with a budget of points.
+    /// A quarter of the budget is spent on a uniform grid, every further point splits the interval
+    /// between two neighbouring densities in which the flow or its spread over the simulations changes most.
+    /// The intervals next to the highest flow count double, so the maximum of the fundamental diagram is resolved.
+    /// # Arguments
+    /// * `start` - The lowest density
+    /// * `end` - The highest density
+    /// * `budget` - The total number of densities to simulate
+    /// # Returns
+    /// The averaged results of every density, sorted by density
+    pub fn run_adaptive_density(&self, start: f32, end: f32, budget: usize) -> Vec<IterationInfo> {
+        // Densities are sampled as numbers of vehicles, so no two points give the same road
+        let cells = (self.road_length * self.lane_speeds.len()) as f32;
+        let first = (start * cells).round() as usize;
+        let last = (end * cells).round() as usize;
+
+        let initial_points =
+            (budget / INITIAL_FRACTION).clamp(MIN_INITIAL_POINTS.min(budget), budget);
+        let mut initial_vehicles = (0..initial_points)
+            .map(|i| first + (last - first) * i / (initial_points - 1))
+            .collect::<Vec<_>>();
+        initial_vehicles.dedup();
+
+        let bar = self.progress_bar(budget as u64);
+
+        // The simulated numbers of vehicles and their results, kept sorted by the number of vehicles
+        let mut points: Vec<(usize, IterationInfo)> = Vec::new();
+
+        for i in 0..budget {
+            if self.was_interrupted() {
+                break;
+            }
+
+            let vehicles = match initial_vehicles.get(i) {
+                Some(vehicles) => *vehicles,
+                None => match next_vehicle_count(&points) {
+                    Some(vehicles) => vehicles,
+                    None => break,
+                },
+            };
+
+            bar.inc(1);
+            // Halfway between two numbers of vehicles, so rounding down in `create_road` gives exactly this number
+            let density = (vehicles as f32 + 0.5) / cells;
+            let point = ParameterPoint {
+                density,
+                ..self.standard_point()
+            };
+            let Some(info) = self.run_averaged_point(i + 1, i + 1, point) else {
+                break;
+            };
+
+            let index = points.partition_point(|(v, _)| *v < vehicles);
+            points.insert(index, (vehicles, info));
+        }
+
+        bar.finish();
+
+        points.into_iter().map(|(_, info)| info).collect()
+    }
+}
+
+/// The number of vehicles in the middle of the interval between two neighbouring points that most needs refining,
+/// or `None` if every interval is already as fine as it can be
+fn next_vehicle_count(points: &[(usize, IterationInfo)]) -> Option<usize> {
+    let flow_spread = |info: &IterationInfo| {
+        info.metric_statistics
+            .iter()
+            .find(|(name, _)| name == "flow")
+            .map(|(_, statistics)| statistics.std_dev)
+            .filter(|std_dev| std_dev.is_finite())
+            .unwrap_or(0.0)
+    };
+
+    let range = |values: Vec<f32>| {
+        let max = values.iter().copied().fold(f32::MIN, f32::max);
+        let min = values.iter().copied().fold(f32::MAX, f32::min);
+        (max - min).max(f32::EPSILON)
+    };
+    let flow_range = range(points.iter().map(|(_, info)| info.flow).collect());
+    let spread_range = range(points.iter().map(|(_, info)| flow_spread(info)).collect());
+
+    let max_flow_index = points
+        .iter()
+        .enumerate()
+        .max_by(|(_, (_, a)), (_, (_, b))| a.flow.total_cmp(&b.flow))
+        .map(|(index, _)| index)?;
+
+    points
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[1].0 - w[0].0 >= 2)
+        .map(|(index, w)| {
+            let (low, high) = (&w[0].1, &w[1].1);
+            let mut score = (high.flow - low.flow).abs() / flow_range
+                + (flow_spread(high) - flow_spread(low)).abs() / spread_range;
+            if index == max_flow_index || index + 1 == max_flow_index {
+                score *= 2.0;
+            }
+            // Wider intervals win ties, so flat regions are still refined evenly
+            (score, w[1].0 - w[0].0, (w[0].0 + w[1].0) / 2)
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+        .map(|(_, _, vehicles)| vehicles)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn point(vehicles: usize, flow: f32) -> (usize, IterationInfo) {
+        let info = IterationInfo {
+            iteration: vehicles,
+            time: Duration::ZERO,
+            average_speed: 0.0,
+            average_speed_per_lane: Vec::new(),
+            vehicle_count: vehicles,
+            density: 0.0,
+            lane_change_probability: 0.0,
+            deceleration_probability: 0.0,
+            max_speed_per_lane: Vec::new(),
+            flow,
+            road_length: 100,
+            lane_changes_left: 0.0,
+            lane_changes_right: 0.0,
+            lane_changes_from_lane: Vec::new(),
+            unsafe_lane_changes: 0.0,
+            lane_changes_per_step: 0.0,
+            lane_change_rate: 0.0,
+            branch: None,
+            metric_statistics: Vec::new(),
+            jam_statistics: None,
+            gap_histograms: None,
+            lane_changes_by_step: None,
+            final_vehicles: Vec::new(),
+        };
+        (vehicles, info)
+    }
+
+    #[test]
+    fn the_interval_with_the_largest_change_in_flow_is_split() {
+        let points = [
+            point(0, 0.0),
+            point(10, 0.1),
+            point(20, 0.9),
+            point(30, 0.95),
+        ];
+        assert_eq!(next_vehicle_count(&points), Some(15));
+    }
+
+    #[test]
+    fn wider_intervals_win_ties() {
+        let points = [point(0, 0.5), point(4, 0.5), point(20, 0.5)];
+        assert_eq!(next_vehicle_count(&points), Some(12));
+    }
+
+    #[test]
+    fn neighbouring_vehicle_counts_are_not_split() {
+        let points = [point(3, 0.1), point(4, 0.5), point(5, 0.2)];
+        assert_eq!(next_vehicle_count(&points), None);
+    }
+}
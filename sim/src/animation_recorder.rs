@@ -0,0 +1,287 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use colored::Color;
+
+use crate::road::LANE_COLORS;
+use crate::typedef::{AnimationFormat, AnimationFrame, AnimationRecorder, Road, WriterError};
+
+const SIDE_OF_ROAD: char = '#';
+const STRIDE: char = '-';
+
+/// The size of a glyph of the GIF font in pixels before scaling
+const GLYPH_SIZE: (usize, usize) = (5, 7);
+/// The size of a pixel of the GIF font
+const GLYPH_SCALE: usize = 2;
+/// The size of one character in a GIF frame, including the space around the glyph
+const CELL_SIZE: (usize, usize) = (
+    GLYPH_SIZE.0 * GLYPH_SCALE + 2,
+    GLYPH_SIZE.1 * GLYPH_SCALE + 4,
+);
+
+/// The colours of a GIF: the background, the side of the road and the strides, and the colours of the lanes
+const GIF_PALETTE: [u8; 15] = [
+    0x00, 0x00, 0x00, // black
+    0x9a, 0x9a, 0x9a, // grey
+    0x3b, 0x8e, 0xea, // blue
+    0x23, 0xd1, 0x8b, // green
+    0xf1, 0x4c, 0x4c, // red
+];
+
+/// One character of a frame, in the colour of a lane or uncoloured
+type Cell = (char, Option<Color>);
+
+impl AnimationRecorder {
+    /// Create a recorder that animates one simulation of one parameter point
+    /// # Arguments
+    /// * `file_path` - The file to write the animation to
+    /// * `format` - The format of the animation
+    /// * `simulation` - The simulation to record, starting at 0
+    /// * `iteration` - The parameter point to record, starting at 1
+    /// * `frame_duration` - How long every step is shown
+    pub fn new(
+        file_path: &Path,
+        format: AnimationFormat,
+        simulation: usize,
+        iteration: usize,
+        frame_duration: Duration,
+    ) -> Self {
+        Self {
+            format,
+            file_path: file_path.to_path_buf(),
+            simulation,
+            iteration,
+            frame_duration,
+            frames: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keep the road as a frame, if it belongs to the recorded simulation and parameter point
+    /// # Arguments
+    /// * `simulation` - The number of the simulation, starting at 0
+    /// * `iteration` - The number of the set of parameters within the simulation
+    /// * `step` - The step the road is in
+    /// * `road` - The road after the step
+    pub fn record(&self, simulation: usize, iteration: usize, step: usize, road: &Road) {
+        if (simulation, iteration) != (self.simulation, self.iteration) {
+            return;
+        }
+
+        let mut lanes = vec![vec![None; road.len as usize]; road.speed_per_lane.len()];
+        for vehicle in &road.vehicles {
+            lanes[vehicle.position.y as usize][vehicle.position.x as usize] =
+                Some((vehicle.velocity.into_inner(), vehicle.original_lane));
+        }
+
+        self.frames.lock().unwrap().push(AnimationFrame {
+            step,
+            lanes,
+            lane_speeds: road
+                .speed_per_lane
+                .iter()
+                .map(|speed| speed.into_inner())
+                .collect(),
+            average_speed: road
+                .vehicles
+                .iter()
+                .map(|v| v.velocity.into_inner() as f32)
+                .sum::<f32>()
+                / road.vehicles.len().max(1) as f32,
+        });
+    }
+
+    /// Write the recorded frames to the animation file, once the recorded simulation has finished
+    /// # Arguments
+    /// * `simulation` - The number of the simulation that finished
+    /// * `iteration` - The number of the set of parameters that finished
+    pub fn finish(&self, simulation: usize, iteration: usize) -> Result<(), WriterError> {
+        if (simulation, iteration) != (self.simulation, self.iteration) {
+            return Ok(());
+        }
+
+        let frames = std::mem::take(&mut *self.frames.lock().unwrap());
+        if frames.is_empty() {
+            return Ok(());
+        }
+
+        let writer = BufWriter::new(File::create(&self.file_path)?);
+        match self.format {
+            AnimationFormat::Cast => write_cast(writer, &frames, self.frame_duration),
+            AnimationFormat::Gif => write_gif(writer, &frames, self.frame_duration),
+        }
+    }
+}
+
+/// The road as characters, laid out like `Road::pretty_print`:
+/// the side of the road, the lanes from left to right with the maximum speed of every lane
+/// and the strides between the lanes
+fn frame_rows(frame: &AnimationFrame) -> Vec<Vec<Cell>> {
+    let len = frame.lanes.first().map_or(0, |lane| lane.len());
+    let side_of_road = vec![(SIDE_OF_ROAD, None); len];
+    let strides = (0..len)
+        .map(|x| (if x % 4 == 0 { STRIDE } else { ' ' }, None))
+        .collect::<Vec<_>>();
+
+    let mut rows = vec![side_of_road.clone()];
+    for (lane, cells) in frame.lanes.iter().enumerate().rev() {
+        let mut row = cells
+            .iter()
+            .map(|cell| match cell {
+                Some((velocity, original_lane)) => (
+                    char::from_digit(*velocity as u32, 10).unwrap_or('+'),
+                    Some(LANE_COLORS[*original_lane as usize]),
+                ),
+                None => (' ', None),
+            })
+            .collect::<Vec<_>>();
+        row.push((' ', None));
+        row.extend(
+            frame.lane_speeds[lane]
+                .to_string()
+                .chars()
+                .map(|c| (c, Some(LANE_COLORS[lane]))),
+        );
+        rows.push(row);
+        if lane > 0 {
+            rows.push(strides.clone());
+        }
+    }
+    rows.push(side_of_road);
+    rows
+}
+
+/// Write an asciicast v2 file, with the step and the average speed above the road
+fn write_cast(
+    mut writer: impl Write,
+    frames: &[AnimationFrame],
+    frame_duration: Duration,
+) -> Result<(), WriterError> {
+    let mut width = 0;
+    let screens = frames
+        .iter()
+        .map(|frame| {
+            let title = format!(
+                "Step {}  Average speed {:.2}",
+                frame.step, frame.average_speed
+            );
+            width = width.max(title.len());
+            let mut lines = vec![title];
+            for row in frame_rows(frame) {
+                width = width.max(row.len());
+                lines.push(
+                    row.iter()
+                        .map(|(c, color)| match color {
+                            Some(color) => format!("\x1b[{}m{c}\x1b[0m", color.to_fg_str()),
+                            None => c.to_string(),
+                        })
+                        .collect(),
+                );
+            }
+            lines
+        })
+        .collect::<Vec<_>>();
+
+    let header = serde_json::json!({
+        "version": 2,
+        "width": width,
+        "height": screens[0].len(),
+        "timestamp": chrono::Utc::now().timestamp(),
+    });
+    writeln!(writer, "{header}")?;
+
+    for (i, lines) in screens.iter().enumerate() {
+        let time = i as f64 * frame_duration.as_secs_f64();
+        let output = format!("\x1b[2J\x1b[H{}", lines.join("\r\n"));
+        writeln!(writer, "{}", serde_json::json!([time, "o", output]))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write an animated GIF that repeats forever, drawing the road with a small bitmap font
+fn write_gif(
+    writer: impl Write,
+    frames: &[AnimationFrame],
+    frame_duration: Duration,
+) -> Result<(), WriterError> {
+    let rows = frame_rows(&frames[0]);
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let width = columns * CELL_SIZE.0;
+    let height = rows.len() * CELL_SIZE.1;
+
+    let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &GIF_PALETTE)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    for frame in frames {
+        let mut pixels = vec![0u8; width * height];
+        for (y, row) in frame_rows(frame).iter().enumerate() {
+            for (x, (c, color)) in row.iter().enumerate() {
+                draw_glyph(&mut pixels, width, (x, y), *c, palette_index(*color));
+            }
+        }
+
+        encoder.write_frame(&gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            // The delay of a GIF frame is in hundredths of a second
+            delay: (frame_duration.as_millis() / 10).clamp(1, u16::MAX as u128) as u16,
+            buffer: Cow::Owned(pixels),
+            ..gif::Frame::default()
+        })?;
+    }
+    Ok(())
+}
+
+/// The entry of the GIF palette of a colour
+fn palette_index(color: Option<Color>) -> u8 {
+    match color {
+        Some(Color::Blue) => 2,
+        Some(Color::Green) => 3,
+        Some(Color::Red) => 4,
+        _ => 1,
+    }
+}
+
+/// Draw a character into the cell at a column and row of a frame
+fn draw_glyph(pixels: &mut [u8], width: usize, (x, y): (usize, usize), c: char, color: u8) {
+    let left = x * CELL_SIZE.0 + (CELL_SIZE.0 - GLYPH_SIZE.0 * GLYPH_SCALE) / 2;
+    let top = y * CELL_SIZE.1 + (CELL_SIZE.1 - GLYPH_SIZE.1 * GLYPH_SCALE) / 2;
+
+    for (glyph_y, bits) in glyph(c).iter().enumerate() {
+        for glyph_x in 0..GLYPH_SIZE.0 {
+            if bits & (1 << (GLYPH_SIZE.0 - 1 - glyph_x)) == 0 {
+                continue;
+            }
+            for dy in 0..GLYPH_SCALE {
+                let row = (top + glyph_y * GLYPH_SCALE + dy) * width;
+                let column = left + glyph_x * GLYPH_SCALE;
+                pixels[row + column..row + column + GLYPH_SCALE].fill(color);
+            }
+        }
+    }
+}
+
+/// The rows of a 5x7 glyph, the leftmost pixel in the highest of the 5 bits.
+/// Only the characters of a frame are drawn, others are left blank.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        '+' => [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00],
+        SIDE_OF_ROAD => [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a],
+        STRIDE => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        _ => [0x00; 7],
+    }
+}
@@ -0,0 +1,336 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::simulation_writer::sibling_file_path;
+use crate::typedef::{
+    BatchConfig, BatchRunSummary, ExperimentConfig, ExperimentError, MetaData, SimulationsHandler,
+};
+
+impl BatchConfig {
+    pub fn from_file(path: &Path) -> Result<Self, ExperimentError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// The experiment of every run: the base experiment with the changes of the run,
+    /// named with the name template and checked
+    pub fn experiments(&self) -> Result<Vec<ExperimentConfig>, ExperimentError> {
+        let experiments = self
+            .runs
+            .iter()
+            .enumerate()
+            .map(|(index, run)| {
+                let mut table = self.base.clone();
+                merge_tables(&mut table, run);
+                let mut experiment: ExperimentConfig = toml::Value::Table(table)
+                    .try_into()
+                    .map_err(|e| ExperimentError::Invalid(format!("run {}: {e}", index + 1)))?;
+
+                if experiment.output.name.is_none() {
+                    let template = self.name.as_deref().ok_or_else(|| {
+                        ExperimentError::Invalid(format!(
+                            "run {} has no output name and the batch has no name template",
+                            index + 1
+                        ))
+                    })?;
+                    experiment.output.name = Some(render_name(template, &experiment, index + 1)?);
+                }
+
+                experiment.validate()?;
+                Ok(experiment)
+            })
+            .collect::<Result<Vec<_>, ExperimentError>>()?;
+
+        // Runs whose results differ only in the extension would still share the files next to them
+        let mut outputs = experiments
+            .iter()
+            .map(|e| {
+                let file_path = e.output_file_path();
+                (sibling_file_path(&file_path, "metadata.toml"), file_path)
+            })
+            .collect::<Vec<_>>();
+        outputs.sort();
+        if let Some(clash) = outputs.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(ExperimentError::Invalid(if clash[0].1 == clash[1].1 {
+                format!("more than one run writes to {}", clash[0].1.display())
+            } else {
+                format!(
+                    "the runs writing to {} and {} write the same files next to their results",
+                    clash[0].1.display(),
+                    clash[1].1.display()
+                )
+            }));
+        }
+
+        Ok(experiments)
+    }
+}
+
+/// Run experiments in parallel, at most `concurrency` at the same time.
+/// Setting `interrupted` stops every running experiment after its current parameter point
+/// and keeps the remaining experiments from starting.
+/// An experiment that panics is reported as failed without stopping the others.
+/// # Arguments
+/// * `experiments` - The checked experiments of the batch
+/// * `concurrency` - How many experiments run at the same time
+/// * `interrupted` - The flag that stops the batch when set
+/// * `on_finished` - Called with the outcome of every experiment as soon as it finishes
+/// # Returns
+/// The outcome of every experiment that was started, in the order in which they finished
+pub fn run_batch(
+    experiments: Vec<ExperimentConfig>,
+    concurrency: usize,
+    interrupted: Arc<AtomicBool>,
+    on_finished: impl Fn(&BatchRunSummary) + Sync,
+) -> Vec<BatchRunSummary> {
+    let queue = Mutex::new(experiments.into_iter().collect::<VecDeque<_>>());
+    let summaries = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.max(1) {
+            scope.spawn(|| loop {
+                if interrupted.load(std::sync::atomic::Ordering::SeqCst) {
+                    break;
+                }
+                let Some(experiment) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+
+                let start = Instant::now();
+                let summary = panic::catch_unwind(AssertUnwindSafe(|| {
+                    run_experiment(&experiment, interrupted.clone())
+                }))
+                .unwrap_or_else(|payload| BatchRunSummary {
+                    name: experiment.output.name.clone().unwrap_or_default(),
+                    points: 0,
+                    duration: start.elapsed(),
+                    interrupted: false,
+                    error: Some(format!("panicked: {}", panic_message(payload.as_ref()))),
+                });
+                on_finished(&summary);
+                summaries.lock().unwrap().push(summary);
+            });
+        }
+    });
+
+    summaries.into_inner().unwrap()
+}
+
+/// Run one experiment of a batch and save its results
+fn run_experiment(experiment: &ExperimentConfig, interrupted: Arc<AtomicBool>) -> BatchRunSummary {
+    let start = Instant::now();
+    let experiment = experiment.clone().resolve(String::new);
+    let name = experiment.output.name.clone().unwrap_or_default();
+
+    let mut handler = match SimulationsHandler::from_experiment(&experiment, false, false, false) {
+        Ok(handler) => handler,
+        Err(e) => {
+            return BatchRunSummary {
+                name,
+                points: 0,
+                duration: start.elapsed(),
+                interrupted: false,
+                error: Some(e.to_string()),
+            }
+        }
+    };
+    handler.show_progress = false;
+    handler.interrupted = interrupted;
+
+    let mut metadata = MetaData::from_experiment(&experiment);
+    let iteration_infos = handler.run_simulations();
+    metadata.wall_time = Some(start.elapsed().as_secs_f64());
+    metadata.interrupted = handler.was_interrupted();
+    let saved = handler
+        .save_simulation_results(&iteration_infos, &metadata)
+        .map_err(ExperimentError::from)
+        .and_then(|()| {
+            if handler.was_interrupted() {
+                Ok(())
+            } else {
+                handler.remove_checkpoint()
+            }
+        });
+
+    BatchRunSummary {
+        name,
+        points: iteration_infos.len(),
+        duration: start.elapsed(),
+        interrupted: handler.was_interrupted(),
+        error: saved
+            .err()
+            .or_else(|| handler.take_output_error())
+            .map(|e| e.to_string()),
+    }
+}
+
+/// The message a panic was started with
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Recursively apply the values of `changes` to `table`, nested tables are merged instead of replaced
+fn merge_tables(table: &mut toml::Table, changes: &toml::Table) {
+    for (key, value) in changes {
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(changes)) => {
+                merge_tables(existing, changes)
+            }
+            _ => {
+                table.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Replace the placeholders in a name template by the values of an experiment
+/// # Arguments
+/// * `template` - The name with placeholders like `probabilities/{p_lane_change}/{l1}{l2}{l3}`
+/// * `experiment` - The experiment providing the values
+/// * `index` - The number of the run within the batch, starting at 1
+pub fn render_name(
+    template: &str,
+    experiment: &ExperimentConfig,
+    index: usize,
+) -> Result<String, ExperimentError> {
+    let lane_speed = |lane: usize| {
+        experiment
+            .road
+            .lane_speeds
+            .get(lane)
+            .map(|speed| speed.to_string())
+            .unwrap_or_default()
+    };
+
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|close| open + close)
+            .ok_or_else(|| {
+                ExperimentError::Invalid(format!(
+                    "unclosed placeholder in name template '{template}'"
+                ))
+            })?;
+        name.push_str(&rest[..open]);
+
+        let value = match &rest[open + 1..close] {
+            "index" => index.to_string(),
+            "parameter" => experiment.sweep.parameter_name().to_string(),
+            "repetitions" => experiment.repetitions.to_string(),
+            "iterations" => experiment.iterations.to_string(),
+            "road_length" => experiment.road.length.to_string(),
+            "density" => format_fraction(experiment.road.standard_density),
+            "p_decel" => format_fraction(experiment.probabilities.deceleration),
+            "p_lane_change" => format_fraction(experiment.probabilities.lane_change),
+            "l1" => lane_speed(0),
+            "l2" => lane_speed(1),
+            "l3" => lane_speed(2),
+            "seed" => experiment
+                .seed
+                .map(|seed| seed.to_string())
+                .unwrap_or_default(),
+            placeholder => {
+                return Err(ExperimentError::Invalid(format!(
+                    "unknown placeholder '{{{placeholder}}}' in name template '{template}'"
+                )))
+            }
+        };
+        name.push_str(&value);
+        rest = &rest[close + 1..];
+    }
+    name.push_str(rest);
+
+    Ok(name)
+}
+
+/// Format a probability or density of a name the way run.ps1 did, keeping the
+/// decimal point of whole numbers so 1.0 is written to `1.0` and not to `1`
+fn format_fraction(value: f32) -> String {
+    format!("{value:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probabilities_batch_keeps_the_paths_of_run_ps1() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../experiments/probabilities.batch.toml");
+        let names = BatchConfig::from_file(&path)
+            .unwrap()
+            .experiments()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.output.name.unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(names.contains(&"probabilities/0.4/555".to_string()));
+        assert!(names.contains(&"probabilities/0.6/255".to_string()));
+        assert!(names.contains(&"probabilities/1.0/535".to_string()));
+    }
+
+    fn batch(runs: &str) -> BatchConfig {
+        toml::from_str(&format!(
+            "name = \"runs/{{l1}}{{l2}}{{l3}}\"\n\
+             [base.sweep]\nparameter = \"density\"\nstart = 0.1\nend = 0.3\nstep = 0.1\n\
+             {runs}"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn runs_writing_to_the_same_results_are_rejected() {
+        let config = batch(
+            "[[runs]]\nroad = { lane_speeds = [5, 5, 5] }\n\
+             [[runs]]\nroad = { lane_speeds = [5, 5, 5] }\n",
+        );
+
+        let error = config.experiments().err().unwrap();
+        assert!(error.to_string().contains("more than one run writes to"));
+    }
+
+    #[test]
+    fn runs_sharing_the_files_next_to_their_results_are_rejected() {
+        let config = batch(
+            "[[runs]]\nroad = { lane_speeds = [5, 5, 5] }\n\
+             [[runs]]\nroad = { lane_speeds = [5, 5, 5] }\noutput = { format = \"json_lines\" }\n",
+        );
+
+        let error = config.experiments().err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("write the same files next to their results"));
+    }
+
+    #[test]
+    fn runs_with_different_names_are_accepted() {
+        let config = batch(
+            "[[runs]]\nroad = { lane_speeds = [5, 5, 5] }\n\
+             [[runs]]\nroad = { lane_speeds = [5, 3, 5] }\n",
+        );
+
+        let names = config
+            .experiments()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.output.name.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["runs/555", "runs/535"]);
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use crate::{
+    simulation_writer::nan_to_zero,
+    typedef::{
+        CalibrationCandidate, CalibrationResult, CalibrationSearch, ExperimentError, IterationInfo,
+        ObservedPoint, ParameterPoint, SimulationsHandler,
+    },
+};
+
+/// The first step of the search, as a fraction of the range of every parameter
+const INITIAL_STEP: f32 = 0.25;
+/// The search has converged once the step is smaller than this fraction of the range of every parameter
+const MIN_STEP: f32 = 1.0 / 64.0;
+
+impl CalibrationSearch {
+    /// The parameter set at a position of the search, every coordinate runs from 0 to 1 between the bounds
+    /// of a parameter: the deceleration probability, the lane change probability and then the lane speeds
+    fn parameters(&self, position: &[f32]) -> (f32, f32, Vec<u8>) {
+        let scale = |(low, high): (f32, f32), x: f32| low + x * (high - low);
+        let (low, high) = self.lane_speed;
+
+        (
+            scale(self.deceleration_probability, position[0]),
+            scale(self.lane_change_probability, position[1]),
+            position[2..]
+                .iter()
+                .map(|x| scale((low as f32, high as f32), *x).round() as u8)
+                .collect(),
+        )
+    }
+}
+
+/// Read an observed fundamental diagram from a csv file with `density`, `flow` and optionally `speed` columns
+pub fn read_observations(file_path: &Path) -> Result<Vec<ObservedPoint>, ExperimentError> {
+    let observations = csv::Reader::from_path(file_path)?
+        .deserialize()
+        .collect::<Result<Vec<ObservedPoint>, _>>()?;
+
+    if observations.is_empty() {
+        return Err(ExperimentError::Invalid(format!(
+            "{} contains no observations",
+            file_path.display()
+        )));
+    }
+    if let Some(point) = observations
+        .iter()
+        .find(|point| !(point.density > 0.0 && point.density <= 1.0))
+    {
+        return Err(ExperimentError::Invalid(format!(
+            "observed densities must be larger than 0 and at most 1 vehicle per cell, got {}",
+            point.density
+        )));
+    }
+
+    Ok(observations)
+}
+
+impl SimulationsHandler {
+    /// Search the parameter set whose fundamental diagram is closest to the observations.
+    /// The search starts in the middle of the bounds and tries a step up and down along every parameter,
+    /// moving to every parameter set that lowers the error. Once no step helps, the step is halved.
+    /// Every parameter set is simulated with the same seeds, so the errors differ only by the parameters.
+    /// The search history is kept for `save_simulation_results`, which writes it to `<name>.calibration.csv`
+    /// and the best parameter set to `<name>.calibration.toml`.
+    /// # Returns
+    /// The averaged results of the best parameter set at every observed density
+    pub fn run_calibration(&self, search: &CalibrationSearch) -> Vec<IterationInfo> {
+        let dimensions = 2 + self.lane_speeds.len();
+        let mut position = vec![0.5; dimensions];
+        let mut step = INITIAL_STEP;
+
+        let bar = self.progress_bar(search.max_evaluations as u64);
+        let mut history = Vec::new();
+
+        // The index of the parameter set at a position within the history, simulating it if it is new
+        let evaluate = |history: &mut Vec<(CalibrationCandidate, Vec<IterationInfo>)>,
+                        position: &[f32]| {
+            let (deceleration_probability, lane_change_probability, lane_speeds) =
+                search.parameters(position);
+            let known = history.iter().position(|(candidate, _)| {
+                candidate.deceleration_probability == deceleration_probability
+                    && candidate.lane_change_probability == lane_change_probability
+                    && candidate.lane_speeds == lane_speeds
+            });
+            if known.is_some() {
+                return known;
+            }
+            if history.len() == search.max_evaluations {
+                return None;
+            }
+
+            bar.inc(1);
+            let evaluation = history.len() + 1;
+            let mut infos = Vec::new();
+            for (index, observed) in self.observations.iter().enumerate() {
+                let point = ParameterPoint {
+                    density: observed.density,
+                    deceleration_probability,
+                    lane_change_probability,
+                    lane_speeds: lane_speeds.clone(),
+                    ..self.standard_point()
+                };
+                let iteration = (evaluation - 1) * self.observations.len() + index + 1;
+                infos.push(self.run_averaged_point(iteration, index + 1, point)?);
+            }
+            if self.was_interrupted() {
+                return None;
+            }
+
+            let candidate = self.calibration_candidate(
+                evaluation,
+                deceleration_probability,
+                lane_change_probability,
+                lane_speeds,
+                &infos,
+            );
+            history.push((candidate, infos));
+            Some(history.len() - 1)
+        };
+
+        let Some(mut best) = evaluate(&mut history, &position) else {
+            bar.finish();
+            return Vec::new();
+        };
+        'search: while step >= MIN_STEP {
+            let mut improved = false;
+            for dimension in 0..dimensions {
+                for direction in [1.0, -1.0] {
+                    let mut candidate = position.clone();
+                    candidate[dimension] =
+                        (candidate[dimension] + direction * step).clamp(0.0, 1.0);
+                    if candidate == position {
+                        continue;
+                    }
+
+                    let Some(index) = evaluate(&mut history, &candidate) else {
+                        break 'search;
+                    };
+                    if history[index].0.error < history[best].0.error {
+                        best = index;
+                        position = candidate;
+                        improved = true;
+                    }
+                }
+            }
+
+            if !improved {
+                step /= 2.0;
+            }
+        }
+
+        bar.finish();
+
+        *self.calibration_history.lock().unwrap() = history
+            .iter()
+            .map(|(candidate, _)| candidate.clone())
+            .collect();
+
+        history.swap_remove(best).1
+    }
+
+    /// The outcome of the calibration that was run, `None` if it did not finish any parameter set
+    pub fn calibration_result(&self) -> Option<CalibrationResult> {
+        let candidates = self.calibration_history.lock().unwrap();
+        // The search only moves to strictly better parameter sets, so the first of the lowest errors is its result
+        let best = candidates
+            .iter()
+            .reduce(|best, candidate| {
+                if candidate.error < best.error {
+                    candidate
+                } else {
+                    best
+                }
+            })?
+            .clone();
+
+        Some(CalibrationResult {
+            evaluations: candidates.len(),
+            interrupted: self.was_interrupted(),
+            best,
+        })
+    }
+
+    /// How far the simulated fundamental diagram of a parameter set is from the observations.
+    /// The speed only counts if every observation has one.
+    fn calibration_candidate(
+        &self,
+        evaluation: usize,
+        deceleration_probability: f32,
+        lane_change_probability: f32,
+        lane_speeds: Vec<u8>,
+        infos: &[IterationInfo],
+    ) -> CalibrationCandidate {
+        let rmse = |pairs: Vec<(f32, f32)>| {
+            (pairs.iter().map(|(a, b)| (a - b).powi(2)).sum::<f32>() / pairs.len() as f32).sqrt()
+        };
+        let mean = |values: Vec<f32>| values.iter().sum::<f32>() / values.len() as f32;
+
+        let flow_rmse = rmse(
+            infos
+                .iter()
+                .zip(&self.observations)
+                .map(|(info, observed)| (info.flow, observed.flow))
+                .collect(),
+        );
+        let mut error =
+            flow_rmse / mean(self.observations.iter().map(|o| o.flow).collect()).max(f32::EPSILON);
+
+        let speeds = self
+            .observations
+            .iter()
+            .map(|o| o.speed)
+            .collect::<Option<Vec<_>>>();
+        let speed_rmse = speeds.map(|speeds| {
+            let speed_rmse = rmse(
+                infos
+                    .iter()
+                    .map(|info| nan_to_zero(info.average_speed))
+                    .zip(speeds.iter().copied())
+                    .collect(),
+            );
+            error += speed_rmse / mean(speeds).max(f32::EPSILON);
+            speed_rmse
+        });
+
+        CalibrationCandidate {
+            evaluation,
+            deceleration_probability,
+            lane_change_probability,
+            lane_speeds,
+            flow_rmse,
+            speed_rmse,
+            error,
+        }
+    }
+}
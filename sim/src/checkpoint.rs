@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::typedef::{
+    Checkpoint, CheckpointRecord, ExperimentConfig, ExperimentError, IterationInfo,
+};
+
+impl Checkpoint {
+    /// Start a new checkpoint file, replacing any previous one
+    /// # Arguments
+    /// * `file_path` - The file to write the checkpoint to
+    /// * `experiment` - The resolved experiment of the run
+    pub fn create(
+        file_path: &Path,
+        experiment: &ExperimentConfig,
+    ) -> Result<Self, ExperimentError> {
+        let mut writer = BufWriter::new(fs::File::create(file_path)?);
+        bincode::serialize_into(&mut writer, &experiment.to_toml()?)?;
+        writer.flush()?;
+
+        Ok(Self {
+            completed: HashMap::new(),
+            file_path: file_path.to_path_buf(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Load the points completed by a previous run and continue writing after them
+    /// # Arguments
+    /// * `file_path` - The checkpoint file of the previous run
+    /// * `experiment` - The resolved experiment of the run, which must be the experiment of the checkpoint
+    pub fn resume(
+        file_path: &Path,
+        experiment: &ExperimentConfig,
+    ) -> Result<Self, ExperimentError> {
+        let mut reader = BufReader::new(fs::File::open(file_path)?);
+
+        let checkpoint_experiment: String = bincode::deserialize_from(&mut reader)?;
+        if checkpoint_experiment != experiment.to_toml()? {
+            return Err(ExperimentError::Invalid(format!(
+                "the checkpoint {} belongs to a different experiment",
+                file_path.display()
+            )));
+        }
+
+        // A record cut off by the end of the previous run is simply simulated again
+        let mut completed = HashMap::new();
+        while let Ok(record) = bincode::deserialize_from::<_, CheckpointRecord>(&mut reader) {
+            completed.insert((record.simulation, record.iteration), record.info);
+        }
+
+        let file = fs::OpenOptions::new().append(true).open(file_path)?;
+
+        Ok(Self {
+            completed,
+            file_path: file_path.to_path_buf(),
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Read the experiment a checkpoint file was written for
+    pub fn read_experiment(file_path: &Path) -> Result<ExperimentConfig, ExperimentError> {
+        if !file_path.exists() {
+            return Err(ExperimentError::Invalid(format!(
+                "there is no checkpoint at {} to resume from",
+                file_path.display()
+            )));
+        }
+
+        let mut reader = BufReader::new(fs::File::open(file_path)?);
+        let experiment: String = bincode::deserialize_from(&mut reader)?;
+
+        Ok(toml::from_str(&experiment)?)
+    }
+
+    /// The result of a point completed by a previous run
+    pub fn completed_point(&self, simulation: usize, iteration: usize) -> Option<&IterationInfo> {
+        self.completed.get(&(simulation, iteration))
+    }
+
+    /// Append a completed point to the checkpoint file and flush it to disk
+    pub fn record(
+        &self,
+        simulation: usize,
+        iteration: usize,
+        info: &IterationInfo,
+    ) -> Result<(), ExperimentError> {
+        let record = CheckpointRecord {
+            simulation,
+            iteration,
+            info: info.clone(),
+        };
+
+        let mut writer = self.writer.lock().unwrap();
+        bincode::serialize_into(&mut *writer, &record)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Delete the checkpoint file once the results of the run have been saved
+    pub fn remove(&self) -> Result<(), ExperimentError> {
+        fs::remove_file(&self.file_path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn experiment(seed: u64) -> ExperimentConfig {
+        toml::from_str(&format!(
+            "seed = {seed}\n[sweep]\nparameter = \"density\"\nstart = 0.1\nend = 0.3\nstep = 0.1\n"
+        ))
+        .unwrap()
+    }
+
+    fn info(iteration: usize, density: f32) -> IterationInfo {
+        IterationInfo {
+            iteration,
+            time: Duration::from_millis(5),
+            average_speed: 2.5,
+            average_speed_per_lane: vec![2.0, 2.5, 3.0],
+            vehicle_count: 30,
+            density,
+            lane_change_probability: 0.6,
+            deceleration_probability: 0.4,
+            max_speed_per_lane: vec![5, 5, 5],
+            flow: 0.25,
+            road_length: 100,
+            lane_changes_left: 1.0,
+            lane_changes_right: 2.0,
+            lane_changes_from_lane: vec![1.0, 1.0, 1.0],
+            unsafe_lane_changes: 0.0,
+            lane_changes_per_step: 0.5,
+            lane_change_rate: 0.1,
+            branch: None,
+            metric_statistics: Vec::new(),
+            jam_statistics: None,
+            gap_histograms: None,
+            lane_changes_by_step: None,
+            final_vehicles: Vec::new(),
+        }
+    }
+
+    fn checkpoint_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sim-{}-{name}.checkpoint", std::process::id()))
+    }
+
+    #[test]
+    fn resume_returns_the_recorded_points() {
+        let path = checkpoint_path("resume");
+        let checkpoint = Checkpoint::create(&path, &experiment(1)).unwrap();
+        checkpoint.record(0, 1, &info(1, 0.1)).unwrap();
+        checkpoint.record(1, 2, &info(2, 0.2)).unwrap();
+        drop(checkpoint);
+
+        let resumed = Checkpoint::resume(&path, &experiment(1)).unwrap();
+        assert_eq!(resumed.completed.len(), 2);
+        assert_eq!(resumed.completed_point(0, 1).unwrap().density, 0.1);
+        assert_eq!(resumed.completed_point(1, 2).unwrap().density, 0.2);
+        assert!(resumed.completed_point(0, 2).is_none());
+
+        // Points completed after resuming are kept for the next resume
+        resumed.record(0, 2, &info(2, 0.2)).unwrap();
+        drop(resumed);
+        let resumed = Checkpoint::resume(&path, &experiment(1)).unwrap();
+        assert_eq!(resumed.completed.len(), 3);
+        resumed.remove().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn resume_rejects_the_checkpoint_of_another_experiment() {
+        let path = checkpoint_path("other");
+        Checkpoint::create(&path, &experiment(1)).unwrap();
+
+        let error = Checkpoint::resume(&path, &experiment(2)).err().unwrap();
+        assert!(error.to_string().contains("different experiment"));
+        assert_eq!(Checkpoint::read_experiment(&path).unwrap().seed, Some(1));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resume_ignores_a_cut_off_record() {
+        let path = checkpoint_path("cut-off");
+        let checkpoint = Checkpoint::create(&path, &experiment(1)).unwrap();
+        checkpoint.record(0, 1, &info(1, 0.1)).unwrap();
+        checkpoint.record(0, 2, &info(2, 0.2)).unwrap();
+        drop(checkpoint);
+
+        let len = fs::metadata(&path).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let resumed = Checkpoint::resume(&path, &experiment(1)).unwrap();
+        assert_eq!(resumed.completed.len(), 1);
+        assert!(resumed.completed_point(0, 1).is_some());
+        resumed.remove().unwrap();
+    }
+}
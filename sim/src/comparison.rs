@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::statistics::{paired_t_test, welch_t_test};
+use crate::typedef::{
+    Capacity, Comparison, IterationInfo, MetricComparison, PointComparison, SampleStatistics,
+    SweepBranch, WriterError,
+};
+
+/// Parameters closer than this are the same parameter point
+const PARAMETER_RESOLUTION: f32 = 1e-5;
+
+/// The parameters that identify a point of a sweep: the density, the deceleration probability,
+/// the lane change probability and the branch of a hysteresis sweep
+type PointKey = (i64, i64, i64, Option<SweepBranch>);
+
+impl Comparison {
+    /// Compare the flow and average speed of run `b` to run `a` at every parameter point simulated in both runs.
+    /// The points are matched by the density and by the probabilities that are swept in either run,
+    /// so runs with different fixed probabilities or lane speeds can be compared.
+    /// # Arguments
+    /// * `a` - The results of the first run
+    /// * `b` - The results of the second run
+    pub fn new(a: &[IterationInfo], b: &[IterationInfo]) -> Self {
+        let swept = |value: fn(&IterationInfo) -> f32| {
+            [a, b].iter().any(|infos| {
+                infos
+                    .iter()
+                    .any(|info| (value(info) - value(&infos[0])).abs() > PARAMETER_RESOLUTION)
+            })
+        };
+        let decel_swept = swept(|info| info.deceleration_probability);
+        let lane_change_swept = swept(|info| info.lane_change_probability);
+
+        let key = |info: &IterationInfo| -> PointKey {
+            let round = |value: f32| (value / PARAMETER_RESOLUTION).round() as i64;
+            (
+                round(info.density),
+                if decel_swept {
+                    round(info.deceleration_probability)
+                } else {
+                    0
+                },
+                if lane_change_swept {
+                    round(info.lane_change_probability)
+                } else {
+                    0
+                },
+                info.branch,
+            )
+        };
+
+        let b_by_key = b
+            .iter()
+            .map(|info| (key(info), info))
+            .collect::<HashMap<_, _>>();
+        let points = a
+            .iter()
+            .filter_map(|info_a| {
+                b_by_key
+                    .get(&key(info_a))
+                    .map(|info_b| compare_point(info_a, info_b))
+            })
+            .collect::<Vec<_>>();
+
+        let differences = |metric: fn(&PointComparison) -> MetricComparison| {
+            points
+                .iter()
+                .map(|p| metric(p).difference)
+                .collect::<Vec<_>>()
+        };
+        let flow_test = paired_t_test(&differences(|p| p.flow));
+        let average_speed_test = paired_t_test(&differences(|p| p.average_speed));
+
+        let capacity_a = capacity_point(a);
+        let capacity_b = capacity_point(b);
+        let capacity_test = capacity_a.zip(capacity_b).and_then(|(a, b)| {
+            welch_t_test(
+                &metric_statistics(a, "flow")?,
+                &metric_statistics(b, "flow")?,
+            )
+        });
+
+        Self {
+            unmatched: (
+                a.len().saturating_sub(points.len()),
+                b.len().saturating_sub(points.len()),
+            ),
+            points,
+            flow_test,
+            average_speed_test,
+            capacity_a: capacity_a.map(capacity),
+            capacity_b: capacity_b.map(capacity),
+            capacity_test,
+        }
+    }
+
+    /// The number of points at which the difference of a metric is significant
+    /// # Arguments
+    /// * `alpha` - The significance level
+    /// * `metric` - The metric of a point
+    pub fn significant_points(
+        &self,
+        alpha: f32,
+        metric: impl Fn(&PointComparison) -> MetricComparison,
+    ) -> usize {
+        self.points
+            .iter()
+            .filter(|p| metric(p).test.is_some_and(|test| test.p_value < alpha))
+            .count()
+    }
+
+    /// Write the comparison of every point to a csv file
+    /// # Arguments
+    /// * `file_path` - The path of the csv file
+    pub fn write_csv(&self, file_path: &Path) -> Result<(), WriterError> {
+        let mut csv = csv::Writer::from_path(file_path)?;
+        csv.write_record([
+            "density",
+            "deceleration_probability",
+            "lane_change_probability",
+            "branch",
+            "flow_a",
+            "flow_b",
+            "flow_difference",
+            "flow_t",
+            "flow_p_value",
+            "average_speed_a",
+            "average_speed_b",
+            "average_speed_difference",
+            "average_speed_t",
+            "average_speed_p_value",
+        ])?;
+
+        for point in &self.points {
+            let branch = match point.branch {
+                Some(SweepBranch::Up) => "up",
+                Some(SweepBranch::Down) => "down",
+                None => "",
+            };
+            let test = |metric: &MetricComparison| {
+                metric
+                    .test
+                    .map_or((f32::NAN, f32::NAN), |test| (test.t, test.p_value))
+            };
+            let (flow_t, flow_p) = test(&point.flow);
+            let (speed_t, speed_p) = test(&point.average_speed);
+            csv.serialize((
+                point.density,
+                point.deceleration_probability,
+                point.lane_change_probability,
+                branch,
+                point.flow.a,
+                point.flow.b,
+                point.flow.difference,
+                flow_t,
+                flow_p,
+                point.average_speed.a,
+                point.average_speed.b,
+                point.average_speed.difference,
+                speed_t,
+                speed_p,
+            ))?;
+        }
+        csv.flush()?;
+        Ok(())
+    }
+}
+
+fn compare_point(a: &IterationInfo, b: &IterationInfo) -> PointComparison {
+    let compare = |name: &str, value: fn(&IterationInfo) -> f32| MetricComparison {
+        a: value(a),
+        b: value(b),
+        difference: value(b) - value(a),
+        test: metric_statistics(a, name)
+            .zip(metric_statistics(b, name))
+            .and_then(|(a, b)| welch_t_test(&a, &b)),
+    };
+
+    PointComparison {
+        density: a.density,
+        deceleration_probability: a.deceleration_probability,
+        lane_change_probability: a.lane_change_probability,
+        branch: a.branch,
+        flow: compare("flow", |info| info.flow),
+        average_speed: compare("average_speed", |info| info.average_speed),
+    }
+}
+
+/// The spread of a metric over the simulations of a point
+fn metric_statistics(info: &IterationInfo, name: &str) -> Option<SampleStatistics> {
+    info.metric_statistics
+        .iter()
+        .find(|(metric, _)| metric == name)
+        .map(|(_, statistics)| *statistics)
+}
+
+/// The point with the highest flow
+pub(crate) fn capacity_point(infos: &[IterationInfo]) -> Option<&IterationInfo> {
+    infos
+        .iter()
+        .filter(|info| info.flow.is_finite())
+        .max_by(|a, b| a.flow.total_cmp(&b.flow))
+}
+
+pub(crate) fn capacity(info: &IterationInfo) -> Capacity {
+    Capacity {
+        density: info.density,
+        flow: info.flow,
+    }
+}
@@ -0,0 +1,658 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::rng::random_seed;
+use crate::simulation_handler::float_range_step;
+use crate::typedef::{
+    default_deceleration_probability, default_lane_change_probability, default_lane_speeds,
+    default_road_length, default_standard_density, CalibrationSearch, ExperimentConfig,
+    ExperimentError, GridAxis, InitialCondition, ParameterGrid, ProbabilityConfig, ResultFormat,
+    RoadConfig, SensitivityDesign, SensitivityFactor, SimulationType, SweepConfig,
+    VehiclePlacement,
+};
+
+/// The highest lane speed a sweep may choose, vehicles accelerate to one above their speed before it is limited
+pub const MAX_LANE_SPEED: u8 = u8::MAX - 1;
+
+impl Default for RoadConfig {
+    fn default() -> Self {
+        Self {
+            length: default_road_length(),
+            lane_speeds: default_lane_speeds(),
+            standard_density: default_standard_density(),
+            initial_condition: InitialCondition::default(),
+            initial_state: None,
+        }
+    }
+}
+
+impl Default for ProbabilityConfig {
+    fn default() -> Self {
+        Self {
+            deceleration: default_deceleration_probability(),
+            lane_change: default_lane_change_probability(),
+        }
+    }
+}
+
+impl RoadConfig {
+    /// Check the length and the lane speeds of the road
+    pub fn validate(&self) -> Result<(), ExperimentError> {
+        let invalid = |message: String| Err(ExperimentError::Invalid(message));
+
+        if self.length == 0 {
+            return invalid("road.length must be at least 1".to_string());
+        }
+        if self.lane_speeds.len() != 3 {
+            return invalid(format!(
+                "road.lane_speeds must contain exactly 3 speeds, got {}",
+                self.lane_speeds.len()
+            ));
+        }
+        if self
+            .lane_speeds
+            .iter()
+            .any(|speed| !(1..=MAX_LANE_SPEED).contains(speed))
+        {
+            return invalid(format!(
+                "road.lane_speeds must all be between 1 and {MAX_LANE_SPEED}, got {:?}",
+                self.lane_speeds
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that a density puts at least one vehicle on the road and at most one vehicle in every cell
+    /// # Arguments
+    /// * `name` - The name of the density in the experiment file
+    /// * `density` - The density to check
+    /// * `road_length` - The length of the road the density is used on
+    pub fn validate_density(
+        &self,
+        name: &str,
+        density: f32,
+        road_length: u8,
+    ) -> Result<(), ExperimentError> {
+        if !(density > 0.0 && density <= 1.0) {
+            return Err(ExperimentError::Invalid(format!(
+                "{name} must be larger than 0 and at most 1, got {density}"
+            )));
+        }
+        // The same number of vehicles as `Road::vehicles_for_density`
+        let vehicles = road_length as f32 * density * self.lane_speeds.len() as f32;
+        if vehicles < 1.0 {
+            return Err(ExperimentError::Invalid(format!(
+                "{name} of {density} puts no vehicle on a road of length {road_length}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl ProbabilityConfig {
+    /// Check that both probabilities are between 0 and 1
+    pub fn validate(&self) -> Result<(), ExperimentError> {
+        let probabilities = [
+            ("probabilities.deceleration", self.deceleration),
+            ("probabilities.lane_change", self.lane_change),
+        ];
+        for (name, p) in probabilities {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(ExperimentError::Invalid(format!(
+                    "{name} must be between 0 and 1, got {p}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GridAxis {
+    pub fn values(&self) -> Vec<f32> {
+        match *self {
+            GridAxis::Values(ref values) => values.clone(),
+            GridAxis::Range { start, end, step } => float_range_step(start, end, step),
+        }
+    }
+}
+
+impl SweepConfig {
+    /// The start, end and step of the swept parameter, `None` for sweeps without a fixed step
+    pub fn range(&self) -> Option<(f32, f32, f32)> {
+        match *self {
+            SweepConfig::Density { start, end, step }
+            | SweepConfig::PDecel { start, end, step }
+            | SweepConfig::PLaneChange { start, end, step }
+            | SweepConfig::Hysteresis { start, end, step } => Some((start, end, step)),
+            SweepConfig::LaneSpeed { .. }
+            | SweepConfig::SpeedDifferential { .. }
+            | SweepConfig::AdaptiveDensity { .. }
+            | SweepConfig::Grid { .. }
+            | SweepConfig::Sensitivity { .. }
+            | SweepConfig::Calibration { .. } => None,
+        }
+    }
+
+    /// The name of the swept parameter, as used in the experiment file
+    pub fn parameter_name(&self) -> &'static str {
+        match self {
+            SweepConfig::Density { .. } => "density",
+            SweepConfig::PDecel { .. } => "p_decel",
+            SweepConfig::PLaneChange { .. } => "p_lane_change",
+            SweepConfig::Hysteresis { .. } => "hysteresis",
+            SweepConfig::LaneSpeed { .. } => "lane_speed",
+            SweepConfig::SpeedDifferential { .. } => "speed_differential",
+            SweepConfig::AdaptiveDensity { .. } => "adaptive_density",
+            SweepConfig::Grid { .. } => "grid",
+            SweepConfig::Sensitivity { .. } => "sensitivity",
+            SweepConfig::Calibration { .. } => "calibration",
+        }
+    }
+}
+
+impl ExperimentConfig {
+    /// Read an experiment from a `.toml` or `.json` file
+    pub fn from_file(path: &Path) -> Result<Self, ExperimentError> {
+        let contents = fs::read_to_string(path)?;
+
+        let config: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            Some("json") => serde_json::from_str(&contents)?,
+            other => {
+                return Err(ExperimentError::UnsupportedFormat(
+                    other.unwrap_or_default().to_string(),
+                ))
+            }
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, ExperimentError> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Whether the sweep sets the density of the road, instead of using `road.standard_density`
+    fn sweeps_density(&self) -> bool {
+        match &self.sweep {
+            SweepConfig::Density { .. }
+            | SweepConfig::Hysteresis { .. }
+            | SweepConfig::AdaptiveDensity { .. }
+            | SweepConfig::Calibration { .. } => true,
+            SweepConfig::Grid { density, .. } => density.is_some(),
+            SweepConfig::Sensitivity { density, .. } => density.is_some(),
+            _ => false,
+        }
+    }
+
+    /// The shortest road of the experiment, which has the fewest vehicles for a density
+    fn shortest_road(&self) -> u8 {
+        match &self.sweep {
+            SweepConfig::Grid {
+                road_length: Some(lengths),
+                ..
+            } => lengths.iter().min().copied().unwrap_or(self.road.length),
+            _ => self.road.length,
+        }
+    }
+
+    /// Check that the experiment can be run
+    pub fn validate(&self) -> Result<(), ExperimentError> {
+        let invalid = |message: String| Err(ExperimentError::Invalid(message));
+
+        if self.repetitions == 0 {
+            return invalid("repetitions must be at least 1".to_string());
+        }
+        self.road.validate()?;
+        self.probabilities.validate()?;
+
+        let initial_condition = &self.road.initial_condition;
+        initial_condition
+            .placement
+            .validate(self.road.lane_speeds.len())
+            .and_then(|_| initial_condition.speeds.validate())
+            .map_err(|message| {
+                ExperimentError::Invalid(format!("road.initial_condition.{message}"))
+            })?;
+
+        if let VehiclePlacement::PerLane { .. } = initial_condition.placement {
+            if self.sweeps_density() {
+                return invalid(format!(
+                    "the {} sweep changes the density, which is fixed by the per lane densities of road.initial_condition",
+                    self.sweep.parameter_name()
+                ));
+            }
+        }
+
+        if self.road.initial_state.is_some() {
+            let sweeps_road = match &self.sweep {
+                SweepConfig::PDecel { .. } | SweepConfig::PLaneChange { .. } => false,
+                SweepConfig::Grid {
+                    density,
+                    lane_speeds,
+                    road_length,
+                    ..
+                } => density.is_some() || lane_speeds.is_some() || road_length.is_some(),
+                SweepConfig::Sensitivity {
+                    density,
+                    lane_speed,
+                    ..
+                } => density.is_some() || lane_speed.is_some(),
+                _ => true,
+            };
+            if sweeps_road {
+                return invalid(format!(
+                    "the {} sweep changes the road, which is fixed by road.initial_state",
+                    self.sweep.parameter_name()
+                ));
+            }
+        }
+
+        if self.output.animation.is_some() {
+            if self.output.animation_simulation >= self.repetitions {
+                return invalid(format!(
+                    "output.animation_simulation must be smaller than the {} repetitions, got {}",
+                    self.repetitions, self.output.animation_simulation
+                ));
+            }
+            if self.output.animation_iteration == Some(0) {
+                return invalid("output.animation_iteration must be at least 1".to_string());
+            }
+        }
+
+        if self.seed.is_some_and(|seed| seed > i64::MAX as u64) {
+            return invalid(format!(
+                "seed must be at most {}, got {}",
+                i64::MAX,
+                self.seed.unwrap_or_default()
+            ));
+        }
+
+        let uses_standard_density = !self.sweeps_density()
+            && self.road.initial_state.is_none()
+            && !matches!(
+                initial_condition.placement,
+                VehiclePlacement::PerLane { .. }
+            );
+        if uses_standard_density {
+            self.road.validate_density(
+                "road.standard_density",
+                self.road.standard_density,
+                self.shortest_road(),
+            )?;
+        }
+        if let VehiclePlacement::PerLane { densities } = &initial_condition.placement {
+            let shortest_road = self.shortest_road();
+            let vehicles = initial_condition.placement.lanes_of_vehicles(
+                shortest_road as usize,
+                0.0,
+                self.road.lane_speeds.len(),
+            );
+            if self.road.initial_state.is_none() && vehicles.is_empty() {
+                return invalid(format!(
+                    "road.initial_condition.placement.densities {densities:?} put no vehicle on a road of length {shortest_road}"
+                ));
+            }
+        }
+
+        if let Some(range) = self.sweep.range() {
+            validate_range("sweep", range)?;
+            if let SweepConfig::Density { .. } | SweepConfig::Hysteresis { .. } = self.sweep {
+                let (start, end, step) = range;
+                for density in float_range_step(start, end, step) {
+                    self.road
+                        .validate_density("sweep density", density, self.road.length)?;
+                }
+            }
+            return Ok(());
+        }
+
+        if let SweepConfig::AdaptiveDensity { start, end, budget } = self.sweep {
+            if budget < 2 {
+                return invalid(format!("sweep.budget must be at least 2, got {budget}"));
+            }
+            if start >= end {
+                return invalid(format!(
+                    "sweep.start must be smaller than sweep.end, got {start} and {end}"
+                ));
+            }
+            // The lowest density has the fewest vehicles
+            self.road
+                .validate_density("sweep.start", start, self.road.length)?;
+            self.road
+                .validate_density("sweep.end", end, self.road.length)?;
+        }
+
+        if let SweepConfig::LaneSpeed { lane, start, end } = self.sweep {
+            if lane >= self.road.lane_speeds.len() {
+                return invalid(format!(
+                    "sweep.lane must be smaller than {}, got {lane}",
+                    self.road.lane_speeds.len()
+                ));
+            }
+            if start == 0 {
+                return invalid("sweep.start must be at least 1".to_string());
+            }
+            if end > MAX_LANE_SPEED {
+                return invalid(format!(
+                    "sweep.end must be at most {MAX_LANE_SPEED}, got {end}"
+                ));
+            }
+            if start > end {
+                return invalid(format!(
+                    "sweep.start must not be larger than sweep.end, got {start} and {end}"
+                ));
+            }
+        }
+
+        if let SweepConfig::SpeedDifferential { start, end } = self.sweep {
+            if start > end {
+                return invalid(format!(
+                    "sweep.start must not be larger than sweep.end, got {start} and {end}"
+                ));
+            }
+            // The rightmost lane is the slowest and must keep a speed of at least 1
+            let lanes = self.road.lane_speeds.len() as i32;
+            let fastest = self.road.lane_speeds[self.road.lane_speeds.len() - 1];
+            if fastest as i32 - end as i32 * (lanes - 1) < 1 {
+                return invalid(format!(
+                    "sweep.end of {end} leaves the rightmost lane without speed, the leftmost lane has a speed of {fastest}"
+                ));
+            }
+        }
+
+        if let SweepConfig::Grid {
+            density,
+            p_decel,
+            p_lane_change,
+            lane_speeds,
+            road_length,
+        } = &self.sweep
+        {
+            let axes = [
+                ("sweep.density", density),
+                ("sweep.p_decel", p_decel),
+                ("sweep.p_lane_change", p_lane_change),
+            ];
+            for (name, axis) in axes {
+                let Some(axis) = axis else {
+                    continue;
+                };
+                if let GridAxis::Range { start, end, step } = *axis {
+                    validate_range(name, (start, end, step))?;
+                }
+                let values = axis.values();
+                if values.is_empty() {
+                    return invalid(format!("{name} must contain at least one value"));
+                }
+                if let Some(value) = values.iter().find(|v| !(0.0..=1.0).contains(*v)) {
+                    return invalid(format!("{name} must be between 0 and 1, got {value}"));
+                }
+            }
+
+            if let Some(lane_speeds) = lane_speeds {
+                if lane_speeds.is_empty() {
+                    return invalid(
+                        "sweep.lane_speeds must contain at least one value".to_string(),
+                    );
+                }
+                if lane_speeds.iter().any(|speeds| speeds.len() != 3) {
+                    return invalid(
+                        "every entry of sweep.lane_speeds must contain exactly 3 speeds"
+                            .to_string(),
+                    );
+                }
+                if lane_speeds
+                    .iter()
+                    .flatten()
+                    .any(|speed| !(1..=MAX_LANE_SPEED).contains(speed))
+                {
+                    return invalid(format!(
+                        "sweep.lane_speeds must all be between 1 and {MAX_LANE_SPEED}"
+                    ));
+                }
+            }
+
+            if let Some(road_length) = road_length {
+                if road_length.is_empty() {
+                    return invalid(
+                        "sweep.road_length must contain at least one value".to_string(),
+                    );
+                }
+                if road_length.contains(&0) {
+                    return invalid("sweep.road_length must all be at least 1".to_string());
+                }
+            }
+
+            if let Some(density) = density {
+                // The densities are combined with every road length, the shortest road has the fewest vehicles
+                let shortest_road = self.shortest_road();
+                for value in density.values() {
+                    self.road
+                        .validate_density("sweep.density", value, shortest_road)?;
+                }
+            }
+        }
+
+        if let SweepConfig::Sensitivity {
+            samples,
+            density,
+            p_decel,
+            p_lane_change,
+            lane_speed,
+        } = self.sweep
+        {
+            if samples < 2 {
+                return invalid(format!("sweep.samples must be at least 2, got {samples}"));
+            }
+
+            if let Some([low, high]) = density {
+                self.road
+                    .validate_density("sweep.density", low, self.road.length)?;
+                self.road
+                    .validate_density("sweep.density", high, self.road.length)?;
+            }
+
+            let bounds = [
+                ("sweep.density", density),
+                ("sweep.p_decel", p_decel),
+                ("sweep.p_lane_change", p_lane_change),
+            ];
+            for (name, bounds) in bounds {
+                let Some([low, high]) = bounds else {
+                    continue;
+                };
+                if !(0.0..=1.0).contains(&low) || !(0.0..=1.0).contains(&high) || low >= high {
+                    return invalid(format!(
+                        "{name} must be an increasing pair of values between 0 and 1, got [{low}, {high}]"
+                    ));
+                }
+            }
+
+            if let Some([low, high]) = lane_speed {
+                if low == 0 || low >= high || high > MAX_LANE_SPEED {
+                    return invalid(format!(
+                        "sweep.lane_speed must be an increasing pair of speeds from 1 to {MAX_LANE_SPEED}, got [{low}, {high}]"
+                    ));
+                }
+            }
+
+            if density.is_none()
+                && p_decel.is_none()
+                && p_lane_change.is_none()
+                && lane_speed.is_none()
+            {
+                return invalid(
+                    "the sensitivity sweep needs bounds for at least one parameter".to_string(),
+                );
+            }
+        }
+
+        if let SweepConfig::Calibration {
+            max_evaluations,
+            p_decel,
+            p_lane_change,
+            lane_speed,
+            ..
+        } = self.sweep
+        {
+            if max_evaluations == 0 {
+                return invalid("sweep.max_evaluations must be at least 1".to_string());
+            }
+
+            let bounds = [
+                ("sweep.p_decel", p_decel),
+                ("sweep.p_lane_change", p_lane_change),
+            ];
+            for (name, [low, high]) in bounds {
+                if !(0.0..=1.0).contains(&low) || !(0.0..=1.0).contains(&high) || low > high {
+                    return invalid(format!(
+                        "{name} must be a pair of values between 0 and 1, the first not larger than the second, got [{low}, {high}]"
+                    ));
+                }
+            }
+
+            let [low, high] = lane_speed;
+            if low == 0 || low > high || high > MAX_LANE_SPEED {
+                return invalid(format!(
+                    "sweep.lane_speed must be a pair of speeds from 1 to {MAX_LANE_SPEED}, the first not larger than the second, got [{low}, {high}]"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The simulation type of the sweep, parameters that are not swept take their value from the experiment
+    pub fn simulation_type(&self) -> SimulationType {
+        match self.sweep {
+            SweepConfig::Density { start, end, step } => SimulationType::Density(start, end, step),
+            SweepConfig::PDecel { start, end, step } => {
+                SimulationType::Deceleration(start, end, step)
+            }
+            SweepConfig::PLaneChange { start, end, step } => {
+                SimulationType::LaneChange(start, end, step)
+            }
+            SweepConfig::Hysteresis { start, end, step } => {
+                SimulationType::Hysteresis(start, end, step)
+            }
+            SweepConfig::AdaptiveDensity { start, end, budget } => {
+                SimulationType::AdaptiveDensity(start, end, budget)
+            }
+            SweepConfig::LaneSpeed { lane, start, end } => {
+                SimulationType::LaneSpeed(lane, start, end)
+            }
+            SweepConfig::SpeedDifferential { start, end } => {
+                SimulationType::SpeedDifferential(start, end)
+            }
+            SweepConfig::Grid {
+                ref density,
+                ref p_decel,
+                ref p_lane_change,
+                ref lane_speeds,
+                ref road_length,
+            } => SimulationType::Grid(ParameterGrid {
+                densities: density
+                    .as_ref()
+                    .map_or_else(|| vec![self.road.standard_density], GridAxis::values),
+                deceleration_probabilities: p_decel
+                    .as_ref()
+                    .map_or_else(|| vec![self.probabilities.deceleration], GridAxis::values),
+                lane_change_probabilities: p_lane_change
+                    .as_ref()
+                    .map_or_else(|| vec![self.probabilities.lane_change], GridAxis::values),
+                lane_speeds: lane_speeds
+                    .clone()
+                    .unwrap_or_else(|| vec![self.road.lane_speeds.clone()]),
+                road_lengths: road_length
+                    .as_ref()
+                    .map_or_else(|| vec![self.road.length], Clone::clone)
+                    .into_iter()
+                    .map(|length| length as usize)
+                    .collect(),
+            }),
+            SweepConfig::Sensitivity {
+                samples,
+                density,
+                p_decel,
+                p_lane_change,
+                lane_speed,
+            } => {
+                let mut factors = Vec::new();
+                if let Some([low, high]) = density {
+                    factors.push(SensitivityFactor::Density(low, high));
+                }
+                if let Some([low, high]) = p_decel {
+                    factors.push(SensitivityFactor::DecelerationProbability(low, high));
+                }
+                if let Some([low, high]) = p_lane_change {
+                    factors.push(SensitivityFactor::LaneChangeProbability(low, high));
+                }
+                if let Some([low, high]) = lane_speed {
+                    factors.extend(
+                        (0..self.road.lane_speeds.len())
+                            .map(|lane| SensitivityFactor::LaneSpeed(lane, low, high)),
+                    );
+                }
+                SimulationType::Sensitivity(SensitivityDesign { samples, factors })
+            }
+            SweepConfig::Calibration {
+                ref observations,
+                max_evaluations,
+                p_decel,
+                p_lane_change,
+                lane_speed,
+            } => SimulationType::Calibration(CalibrationSearch {
+                observations: observations.clone(),
+                max_evaluations,
+                deceleration_probability: (p_decel[0], p_decel[1]),
+                lane_change_probability: (p_lane_change[0], p_lane_change[1]),
+                lane_speed: (lane_speed[0], lane_speed[1]),
+            }),
+        }
+    }
+
+    /// Fill in everything that is chosen at run time, so the resolved experiment can be repeated exactly
+    /// # Arguments
+    /// * `default_name` - Creates the output name if the experiment does not have one
+    pub fn resolve(mut self, default_name: impl FnOnce() -> String) -> Self {
+        self.seed = Some(self.seed.unwrap_or_else(random_seed));
+        if self.output.name.is_none() {
+            self.output.name = Some(default_name());
+        }
+        self
+    }
+
+    /// The file the averaged results are written to, every other output is written next to it
+    pub fn output_file_path(&self) -> PathBuf {
+        if let Some(path) = &self.output.path {
+            return path.clone();
+        }
+
+        let extension = match self.output.format {
+            ResultFormat::Csv | ResultFormat::Stdout => "csv",
+            ResultFormat::JsonLines => "jsonl",
+        };
+        PathBuf::from("data").join(format!(
+            "{}.{extension}",
+            self.output.name.as_deref().unwrap_or_default()
+        ))
+    }
+}
+
+/// Check that a range has a positive step and ends after it starts
+fn validate_range(name: &str, (start, end, step): (f32, f32, f32)) -> Result<(), ExperimentError> {
+    if step <= 0.0 {
+        return Err(ExperimentError::Invalid(format!(
+            "{name}.step must be positive, got {step}"
+        )));
+    }
+    if start >= end {
+        return Err(ExperimentError::Invalid(format!(
+            "{name}.start must be smaller than {name}.end, got {start} and {end}"
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use crate::typedef::{GapHistograms, Road};
+
+/// Width of the time headway bins, in steps
+pub const HEADWAY_BIN_WIDTH: f32 = 0.5;
+
+impl GapHistograms {
+    pub fn new(lanes: usize) -> Self {
+        Self {
+            gaps_per_lane: vec![BTreeMap::new(); lanes],
+            headways_per_lane: vec![BTreeMap::new(); lanes],
+            headway_bin_width: HEADWAY_BIN_WIDTH,
+        }
+    }
+
+    /// Add the gaps and time headways of all vehicles on the road.
+    /// Vehicles without a vehicle in front of them are skipped,
+    /// stopped vehicles have an infinite time headway and are only counted in the gap histogram.
+    pub fn record(&mut self, road: &Road) {
+        for lane in 0..self.gaps_per_lane.len() {
+            for (vehicle, gap) in road.get_gaps_in_lane(lane as u8) {
+                if gap == u8::MAX {
+                    continue;
+                }
+
+                *self.gaps_per_lane[lane].entry(gap).or_insert(0) += 1;
+
+                let velocity = vehicle.velocity.into_inner();
+                if velocity > 0 {
+                    // The number of steps until the vehicle reaches the current position of its leader
+                    let headway = (gap as f32 + 1.0) / velocity as f32;
+                    let bin = (headway / self.headway_bin_width) as usize;
+                    *self.headways_per_lane[lane].entry(bin).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    /// Combine the histograms of another simulation into these histograms
+    pub fn merge(&mut self, other: &GapHistograms) {
+        for (own, other) in self.gaps_per_lane.iter_mut().zip(&other.gaps_per_lane) {
+            for (gap, count) in other {
+                *own.entry(*gap).or_insert(0) += count;
+            }
+        }
+        for (own, other) in self
+            .headways_per_lane
+            .iter_mut()
+            .zip(&other.headways_per_lane)
+        {
+            for (bin, count) in other {
+                *own.entry(*bin).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// The gap histogram of all lanes together
+    pub fn gaps_overall(&self) -> BTreeMap<u8, usize> {
+        let mut overall = BTreeMap::new();
+        for (gap, count) in self.gaps_per_lane.iter().flatten() {
+            *overall.entry(*gap).or_insert(0) += count;
+        }
+        overall
+    }
+
+    /// The time headway histogram of all lanes together
+    pub fn headways_overall(&self) -> BTreeMap<usize, usize> {
+        let mut overall = BTreeMap::new();
+        for (bin, count) in self.headways_per_lane.iter().flatten() {
+            *overall.entry(*bin).or_insert(0) += count;
+        }
+        overall
+    }
+}
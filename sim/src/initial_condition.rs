@@ -0,0 +1,97 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::typedef::{InitialSpeeds, VehiclePlacement, Velocity};
+
+impl VehiclePlacement {
+    /// The lane of every vehicle of a new road, vehicles without a fixed lane are spread over the lanes in turn
+    /// # Arguments
+    /// * `length` - The number of cells in every lane
+    /// * `density` - The density of the road, ignored when every lane has its own density
+    /// * `lanes` - The number of lanes
+    pub fn lanes_of_vehicles(&self, length: usize, density: f32, lanes: usize) -> Vec<usize> {
+        match self {
+            VehiclePlacement::PerLane { densities } => densities
+                .iter()
+                .enumerate()
+                .flat_map(|(lane, density)| {
+                    std::iter::repeat_n(lane, (length as f32 * density) as usize)
+                })
+                .collect(),
+            _ => {
+                let amount_of_cars = (length as f32 * density * lanes as f32) as usize;
+                (0..amount_of_cars).map(|i| i % lanes).collect()
+            }
+        }
+    }
+
+    /// Whether the lane of a vehicle is drawn at random instead of taken from `lanes_of_vehicles`
+    pub fn random_lane(&self) -> bool {
+        *self == VehiclePlacement::Random
+    }
+
+    /// Whether the cell of a vehicle within its lane is drawn at random
+    pub fn random_cell(&self) -> bool {
+        matches!(
+            self,
+            VehiclePlacement::Random | VehiclePlacement::PerLane { .. }
+        )
+    }
+
+    /// The cell of a vehicle that is not placed at random
+    /// # Arguments
+    /// * `index` - The number of vehicles placed in the lane before this one
+    /// * `count` - The number of vehicles in the lane
+    /// * `length` - The number of cells in the lane
+    pub fn fixed_cell(&self, index: usize, count: usize, length: usize) -> u8 {
+        match self {
+            VehiclePlacement::Uniform => (index * length / count) as u8,
+            _ => index as u8,
+        }
+    }
+
+    pub fn validate(&self, lanes: usize) -> Result<(), String> {
+        if let VehiclePlacement::PerLane { densities } = self {
+            if densities.len() != lanes {
+                return Err(format!(
+                    "placement.densities must contain exactly {lanes} densities, got {}",
+                    densities.len()
+                ));
+            }
+            if let Some(density) = densities.iter().find(|d| !(0.0..=1.0).contains(*d)) {
+                return Err(format!(
+                    "placement.densities must be between 0 and 1, got {density}"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl InitialSpeeds {
+    /// Draw the speed of a vehicle in a lane with the given maximum speed
+    pub fn sample(&self, max_speed: u8, rng: &mut impl Rng) -> Velocity {
+        let speed = match self {
+            InitialSpeeds::Random => rng.gen_range(0..max_speed) + 1,
+            InitialSpeeds::Stopped => 0,
+            InitialSpeeds::Max => max_speed,
+            InitialSpeeds::Distribution { weights } => {
+                let distribution = WeightedIndex::new(weights).unwrap();
+                (distribution.sample(rng) as u8).min(max_speed)
+            }
+        };
+
+        Velocity::new(speed)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let InitialSpeeds::Distribution { weights } = self {
+            if let Err(e) = WeightedIndex::new(weights) {
+                return Err(format!("speeds.weights are not a valid distribution: {e}"));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,19 @@
-use crate::typedef::{IterationInfo, Road};
+use crate::typedef::{IterationInfo, ParameterPoint, Road};
 use std::time::Duration;
 
 impl IterationInfo {
-    pub fn new(iteration: usize, time: Duration, road: Road) -> Self {
+    /// Measure a road at the end of a simulation
+    /// # Arguments
+    /// * `iteration` - The number of the set of parameters
+    /// * `time` - How long the simulation took
+    /// * `road` - The road after the last step
+    /// * `point` - The parameters the road was created with
+    pub fn new(iteration: usize, time: Duration, road: &Road, point: &ParameterPoint) -> Self {
         let average_speed = road.get_average_speed();
         let average_speed_per_lane = road.get_average_speed_per_lane();
         let vehicle_count = road.vehicles.len();
         let density = road.get_density();
-        let lane_change_probability = road.vehicles[0].move_left_chance;
+        let lane_change_probability = point.lane_change_probability;
         let deceleration_probability = road.deceleration_probability;
         let max_speed_per_lane = road
             .speed_per_lane
@@ -15,6 +21,7 @@ impl IterationInfo {
             .map(|v| v.into_inner())
             .collect::<Vec<_>>();
         let flow = road.get_flow();
+        let lane_change_counts = &road.lane_change_counts;
 
         Self {
             iteration,
@@ -27,6 +34,23 @@ impl IterationInfo {
             deceleration_probability,
             max_speed_per_lane,
             flow,
+            road_length: road.len as usize,
+            lane_changes_left: lane_change_counts.left as f32,
+            lane_changes_right: lane_change_counts.right as f32,
+            lane_changes_from_lane: lane_change_counts
+                .from_lane
+                .iter()
+                .map(|c| *c as f32)
+                .collect(),
+            unsafe_lane_changes: lane_change_counts.total_unsafe() as f32,
+            lane_changes_per_step: lane_change_counts.per_step_average(),
+            lane_change_rate: lane_change_counts.rate(),
+            branch: None,
+            metric_statistics: Vec::new(),
+            jam_statistics: None,
+            gap_histograms: None,
+            lane_changes_by_step: None,
+            final_vehicles: Vec::new(),
         }
     }
 
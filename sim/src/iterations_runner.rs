@@ -3,20 +3,114 @@ use std::time::{Duration, Instant};
 
 use crate::{
     step,
-    typedef::{IterationInfo, Road},
+    typedef::{GapHistograms, IterationInfo, JamTracker, ParameterPoint, Road, SimulationsHandler},
 };
 
-pub fn run_iterations(sim_nr: usize, iterations: usize, mut road: Road, pretty_print: bool) -> IterationInfo {
+/// Run the steps of one simulation, recording what the handler asks for
+/// # Arguments
+/// * `handler` - The handler running the simulation, which decides what is recorded
+/// * `simulation` - The number of the simulation, starting at 0
+/// * `sim_nr` - The number of the set of parameters within the simulation
+/// * `iterations` - The number of steps to run
+/// * `road` - The initial state of the road
+/// * `point` - The parameters the road was created with
+/// # Returns
+/// The measurements of the simulation and the final state of the road
+pub fn run_iterations(
+    handler: &SimulationsHandler,
+    simulation: usize,
+    sim_nr: usize,
+    iterations: usize,
+    mut road: Road,
+    point: &ParameterPoint,
+) -> (IterationInfo, Road) {
     let start = Instant::now();
-    for _ in 0..iterations {
+    let mut jam_tracker = handler.track_jams.then(JamTracker::default);
+    let trajectory_recorder = handler.trajectory_recorder.as_ref();
+    let animation_recorder = handler.animation_recorder.as_ref();
+    let mut gap_histograms = handler
+        .record_gap_histograms
+        .then(|| GapHistograms::new(road.speed_per_lane.len()));
+    let mut lane_changes_by_step = handler
+        .record_lane_change_steps
+        .then(|| Vec::with_capacity(iterations));
+
+    record_outputs(handler, simulation, sim_nr, 0, &road);
+
+    for i in 0..iterations {
         // road.pretty_print();
+        let lane_changes_before = road.lane_change_counts.total();
         road = step(road);
 
-        if pretty_print {
+        if let Some(lane_changes_by_step) = lane_changes_by_step.as_mut() {
+            lane_changes_by_step
+                .push((road.lane_change_counts.total() - lane_changes_before) as f32);
+        }
+
+        if let Some(jam_tracker) = jam_tracker.as_mut() {
+            jam_tracker.observe(i, &road);
+        }
+
+        record_outputs(handler, simulation, sim_nr, i + 1, &road);
+
+        if let Some(gap_histograms) = gap_histograms.as_mut() {
+            if i >= handler.warmup_steps {
+                gap_histograms.record(&road);
+            }
+        }
+
+        if handler.pretty_print {
             road.pretty_print();
             sleep(Duration::from_millis(150));
         }
     }
 
-    IterationInfo::new(sim_nr, start.elapsed(), road)
+    let mut iteration_info = IterationInfo::new(sim_nr, start.elapsed(), &road, point);
+    iteration_info.jam_statistics = jam_tracker.map(|jam_tracker| jam_tracker.finish(road.len));
+    if handler.record_vehicle_statistics {
+        iteration_info.final_vehicles = vec![road.vehicles.clone()];
+    }
+    iteration_info.gap_histograms = gap_histograms;
+    iteration_info.lane_changes_by_step = lane_changes_by_step;
+
+    if handler.is_recording() {
+        let finished = trajectory_recorder
+            .map_or(Ok(()), |recorder| recorder.flush())
+            .and_then(|()| {
+                animation_recorder.map_or(Ok(()), |recorder| recorder.finish(simulation, sim_nr))
+            });
+        if let Err(e) = finished {
+            handler.output_failed(e.into());
+        }
+    }
+
+    (iteration_info, road)
+}
+
+/// Record the trajectories, the animation frame and the road state of a step, as far as the handler asks for them.
+/// The first output that cannot be written stops the recording and the run.
+fn record_outputs(
+    handler: &SimulationsHandler,
+    simulation: usize,
+    sim_nr: usize,
+    step: usize,
+    road: &Road,
+) {
+    if !handler.is_recording() {
+        return;
+    }
+
+    if let Some(recorder) = &handler.trajectory_recorder {
+        if let Err(e) = recorder.record(simulation, sim_nr, step, road) {
+            return handler.output_failed(e.into());
+        }
+    }
+    if let Some(recorder) = &handler.animation_recorder {
+        recorder.record(simulation, sim_nr, step, road);
+    }
+    if handler.dump_state_at == Some(step) {
+        if let Err(e) = handler.dump_state(simulation, sim_nr, step, road) {
+            handler.output_failed(e.into());
+        }
+    }
 }
@@ -0,0 +1,397 @@
+use crate::typedef::{Jam, JamCluster, JamStatistics, JamTracker, Road, Vehicle};
+
+impl Default for JamTracker {
+    fn default() -> Self {
+        Self::new(1, 1, 2)
+    }
+}
+
+impl JamTracker {
+    pub fn new(max_jam_velocity: u8, max_jam_gap: u8, min_jam_size: usize) -> Self {
+        Self {
+            max_jam_velocity,
+            max_jam_gap,
+            min_jam_size,
+            active_jams: Vec::new(),
+            finished_jams: Vec::new(),
+            next_jam_id: 0,
+        }
+    }
+
+    /// Find the jams on the road after a step and match them with the jams of the previous step.
+    /// A jam that cannot be matched to any cluster has dissolved, a cluster that cannot be matched
+    /// to any jam is a newly born jam.
+    /// # Arguments
+    /// * `step` - The step the road is in
+    /// * `road` - The road after the step
+    pub fn observe(&mut self, step: usize, road: &Road) {
+        let mut clusters = (0..road.speed_per_lane.len() as u8)
+            .flat_map(|lane| self.find_clusters(road, lane))
+            .collect::<Vec<_>>();
+
+        // Larger clusters get the first pick, so when a jam splits the largest part keeps its identity
+        clusters.sort_by_key(|c| std::cmp::Reverse(c.size));
+
+        // A jam front never moves more than the maximum speed of its lane per step
+        let margin = road
+            .speed_per_lane
+            .iter()
+            .map(|v| v.into_inner())
+            .max()
+            .unwrap_or(0);
+
+        let mut previous_jams = std::mem::take(&mut self.active_jams)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        for cluster in clusters {
+            let matched = previous_jams
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, jam)| jam.as_ref().map(|jam| (idx, jam)))
+                .filter(|(_, jam)| {
+                    jam.lane == cluster.lane
+                        && clusters_overlap(&jam.last_cluster, &cluster, margin, road.len)
+                })
+                .max_by_key(|(_, jam)| jam.last_cluster.size)
+                .map(|(idx, _)| idx);
+
+            match matched.and_then(|idx| previous_jams[idx].take()) {
+                Some(mut jam) => {
+                    jam.grow(step, cluster);
+                    self.active_jams.push(jam);
+                }
+                None => {
+                    self.active_jams
+                        .push(Jam::new(self.next_jam_id, step, cluster));
+                    self.next_jam_id += 1;
+                }
+            }
+        }
+
+        for mut jam in previous_jams.into_iter().flatten() {
+            jam.dissolved = true;
+            self.finished_jams.push(jam);
+        }
+    }
+
+    /// Find all clusters of slow vehicles with small gaps between them in a lane
+    pub fn find_clusters(&self, road: &Road, lane: u8) -> Vec<JamCluster> {
+        let gaps = road.get_gaps_in_lane(lane);
+        let is_slow = |idx: usize| gaps[idx].0.velocity.into_inner() <= self.max_jam_velocity;
+        // Whether the vehicle at idx and the vehicle in front of it belong to the same cluster
+        let is_linked = |idx: usize| {
+            gaps.len() > 1
+                && is_slow(idx)
+                && is_slow((idx + 1) % gaps.len())
+                && gaps[idx].1 <= self.max_jam_gap
+        };
+
+        if gaps.is_empty() {
+            return Vec::new();
+        }
+
+        // Start at a vehicle that is not linked to the vehicle behind it, so no cluster is split in two
+        let start =
+            match (0..gaps.len()).find(|&idx| !is_linked((idx + gaps.len() - 1) % gaps.len())) {
+                Some(start) => start,
+                None if gaps.len() >= self.min_jam_size => {
+                    // Every vehicle in the lane is part of one jam spanning the whole lane
+                    return vec![JamCluster {
+                        lane,
+                        upstream_front: gaps[0].0.position.x,
+                        length: road.len,
+                        size: gaps.len(),
+                    }];
+                }
+                None => return Vec::new(),
+            };
+
+        let mut clusters = Vec::new();
+        let mut current: Option<(usize, usize)> = None;
+
+        for offset in 0..gaps.len() {
+            let idx = (start + offset) % gaps.len();
+
+            if let Some((first, size)) = current.as_mut() {
+                *size += 1;
+                if !is_linked(idx) {
+                    clusters.push(self.new_cluster(road, lane, &gaps, *first, idx, *size));
+                    current = None;
+                }
+            } else if is_slow(idx) {
+                if is_linked(idx) {
+                    current = Some((idx, 1));
+                } else {
+                    clusters.push(self.new_cluster(road, lane, &gaps, idx, idx, 1));
+                }
+            }
+        }
+
+        clusters
+            .into_iter()
+            .filter(|c| c.size >= self.min_jam_size)
+            .collect()
+    }
+
+    fn new_cluster(
+        &self,
+        road: &Road,
+        lane: u8,
+        gaps: &[(&Vehicle, u8)],
+        first: usize,
+        last: usize,
+        size: usize,
+    ) -> JamCluster {
+        let upstream_front = gaps[first].0.position.x;
+        let downstream_front = gaps[last].0.position.x;
+
+        let length = if size == 1 {
+            1
+        } else {
+            road.dist_between_vehicles(downstream_front, upstream_front)
+                .saturating_add(2)
+        };
+
+        JamCluster {
+            lane,
+            upstream_front,
+            length,
+            size,
+        }
+    }
+
+    /// Stop tracking and summarise all jams seen.
+    /// Jams that are still present at the end of the simulation are counted, but not as dissolved.
+    pub fn finish(mut self, road_len: u8) -> JamStatistics {
+        self.finished_jams.append(&mut self.active_jams);
+
+        let mut statistics = JamStatistics::default();
+        for jam in &self.finished_jams {
+            statistics.add_jam(jam, road_len);
+        }
+
+        statistics
+    }
+}
+
+impl Jam {
+    pub fn new(id: usize, birth: usize, cluster: JamCluster) -> Self {
+        Self {
+            id,
+            lane: cluster.lane,
+            birth,
+            last_seen: birth,
+            dissolved: false,
+            sizes: vec![cluster.size],
+            upstream_fronts: vec![cluster.upstream_front],
+            last_cluster: cluster,
+        }
+    }
+
+    fn grow(&mut self, step: usize, cluster: JamCluster) {
+        self.last_seen = step;
+        self.sizes.push(cluster.size);
+        self.upstream_fronts.push(cluster.upstream_front);
+        self.last_cluster = cluster;
+    }
+
+    /// The number of steps the jam has been observed
+    pub fn lifetime(&self) -> usize {
+        self.last_seen - self.birth + 1
+    }
+
+    pub fn peak_size(&self) -> usize {
+        self.sizes.iter().copied().max().unwrap_or(0)
+    }
+
+    /// The average velocity of the upstream front of the jam in cells per step.
+    /// Jams move against the direction of traffic, so this is normally negative.
+    /// Returns `None` for jams that were only observed during a single step.
+    pub fn upstream_front_velocity(&self, road_len: u8) -> Option<f32> {
+        if self.upstream_fronts.len() < 2 {
+            return None;
+        }
+
+        let displacement = self
+            .upstream_fronts
+            .windows(2)
+            .map(|w| signed_displacement(w[0], w[1], road_len))
+            .sum::<i32>();
+
+        Some(displacement as f32 / (self.upstream_fronts.len() - 1) as f32)
+    }
+}
+
+impl JamStatistics {
+    pub fn add_jam(&mut self, jam: &Jam, road_len: u8) {
+        self.jam_count += 1;
+        if jam.dissolved {
+            self.dissolved_count += 1;
+        }
+
+        *self.size_distribution.entry(jam.peak_size()).or_insert(0) += 1;
+        *self
+            .lifetime_distribution
+            .entry(jam.lifetime())
+            .or_insert(0) += 1;
+
+        if let Some(velocity) = jam.upstream_front_velocity(road_len) {
+            self.wave_velocity_sum += velocity;
+            self.wave_velocity_samples += 1;
+        }
+    }
+
+    /// Combine the statistics of another simulation into these statistics
+    pub fn merge(&mut self, other: &JamStatistics) {
+        self.jam_count += other.jam_count;
+        self.dissolved_count += other.dissolved_count;
+        for (size, count) in &other.size_distribution {
+            *self.size_distribution.entry(*size).or_insert(0) += count;
+        }
+        for (lifetime, count) in &other.lifetime_distribution {
+            *self.lifetime_distribution.entry(*lifetime).or_insert(0) += count;
+        }
+        self.wave_velocity_sum += other.wave_velocity_sum;
+        self.wave_velocity_samples += other.wave_velocity_samples;
+    }
+
+    pub fn mean_size(&self) -> f32 {
+        weighted_mean(&self.size_distribution)
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.size_distribution.keys().last().copied().unwrap_or(0)
+    }
+
+    pub fn mean_lifetime(&self) -> f32 {
+        weighted_mean(&self.lifetime_distribution)
+    }
+
+    pub fn mean_wave_velocity(&self) -> f32 {
+        if self.wave_velocity_samples == 0 {
+            return 0.0;
+        }
+        self.wave_velocity_sum / self.wave_velocity_samples as f32
+    }
+}
+
+fn weighted_mean(distribution: &std::collections::BTreeMap<usize, usize>) -> f32 {
+    let count = distribution.values().sum::<usize>();
+    if count == 0 {
+        return 0.0;
+    }
+
+    distribution
+        .iter()
+        .map(|(value, count)| (value * count) as f32)
+        .sum::<f32>()
+        / count as f32
+}
+
+/// The shortest movement from `from` to `to` on a circular road, negative when moving backwards
+fn signed_displacement(from: u8, to: u8, road_len: u8) -> i32 {
+    let len = road_len as i32;
+    let forward = (to as i32 - from as i32).rem_euclid(len);
+    if forward > len / 2 {
+        forward - len
+    } else {
+        forward
+    }
+}
+
+/// Check if two clusters on a circular road overlap once the first one is widened by `margin` cells on both sides
+fn clusters_overlap(a: &JamCluster, b: &JamCluster, margin: u8, road_len: u8) -> bool {
+    let len = road_len as i32;
+    let a_start = (a.upstream_front as i32 - margin as i32).rem_euclid(len);
+    let a_len = a.length as i32 + 2 * margin as i32;
+    let b_start = b.upstream_front as i32;
+    let b_len = b.length as i32;
+
+    if a_len >= len || b_len >= len {
+        return true;
+    }
+
+    (b_start - a_start).rem_euclid(len) < a_len || (a_start - b_start).rem_euclid(len) < b_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typedef::{Position, Velocity};
+
+    /// A road of 50 cells with lanes of speed 5 and a stopped vehicle at every position in lane 0
+    fn road_with_stopped_vehicles(positions: &[u8]) -> Road {
+        let vehicles = positions
+            .iter()
+            .enumerate()
+            .map(|(id, &x)| Vehicle::new(id, Position { x, y: 0 }, None, 0.0, 0.0))
+            .collect();
+        Road::new(50, 0.0, vehicles, vec![Velocity::new(5); 3])
+    }
+
+    #[test]
+    fn find_clusters_joins_a_jam_across_the_end_of_the_road() {
+        let tracker = JamTracker::default();
+        let road = road_with_stopped_vehicles(&[48, 49, 0, 1, 20]);
+
+        let clusters = tracker.find_clusters(&road, 0);
+        assert_eq!(
+            clusters,
+            vec![JamCluster {
+                lane: 0,
+                upstream_front: 48,
+                length: 4,
+                size: 4,
+            }]
+        );
+    }
+
+    #[test]
+    fn a_jam_moving_upstream_keeps_its_identity() {
+        let mut tracker = JamTracker::default();
+        tracker.observe(0, &road_with_stopped_vehicles(&[10, 11, 12]));
+        tracker.observe(1, &road_with_stopped_vehicles(&[9, 10, 11]));
+        tracker.observe(2, &road_with_stopped_vehicles(&[8, 9, 10]));
+
+        assert_eq!(tracker.active_jams.len(), 1);
+        assert!(tracker.finished_jams.is_empty());
+        let jam = &tracker.active_jams[0];
+        assert_eq!(jam.lifetime(), 3);
+        assert_eq!(jam.upstream_front_velocity(50), Some(-1.0));
+
+        let statistics = tracker.finish(50);
+        assert_eq!(statistics.jam_count, 1);
+        assert_eq!(statistics.dissolved_count, 0);
+    }
+
+    #[test]
+    fn a_cluster_far_from_every_jam_is_a_new_jam() {
+        let mut tracker = JamTracker::default();
+        tracker.observe(0, &road_with_stopped_vehicles(&[10, 11, 12]));
+        tracker.observe(1, &road_with_stopped_vehicles(&[30, 31, 32]));
+
+        assert_eq!(tracker.active_jams.len(), 1);
+        assert_eq!(tracker.active_jams[0].id, 1);
+        assert_eq!(tracker.active_jams[0].birth, 1);
+        assert_eq!(tracker.finished_jams.len(), 1);
+        assert!(tracker.finished_jams[0].dissolved);
+    }
+
+    #[test]
+    fn the_largest_part_of_a_split_jam_keeps_its_identity() {
+        let mut tracker = JamTracker::default();
+        tracker.observe(0, &road_with_stopped_vehicles(&[10, 11, 12, 13, 14]));
+        tracker.observe(1, &road_with_stopped_vehicles(&[9, 10, 11, 15, 16]));
+
+        let mut jams = tracker
+            .active_jams
+            .iter()
+            .map(|jam| (jam.id, jam.last_cluster.size))
+            .collect::<Vec<_>>();
+        jams.sort();
+        assert_eq!(jams, vec![(0, 3), (1, 2)]);
+        assert!(tracker.finished_jams.is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::typedef::{LaneChangeCounts, LaneChangeDirection, LaneChangeEvent, Vehicle};
+
+impl LaneChangeCounts {
+    pub fn new(lanes: usize) -> Self {
+        Self {
+            from_lane: vec![0; lanes],
+            ..Default::default()
+        }
+    }
+
+    /// Count the lane change events of all vehicles after a step
+    pub fn record_step(&mut self, vehicles: &[Vehicle]) {
+        for event in vehicles.iter().flat_map(|v| v.lane_change_events.iter()) {
+            match *event {
+                LaneChangeEvent::Changed {
+                    direction,
+                    from_lane,
+                } => {
+                    match direction {
+                        LaneChangeDirection::Left => self.left += 1,
+                        LaneChangeDirection::Right => self.right += 1,
+                    }
+                    if let Some(count) = self.from_lane.get_mut(from_lane as usize) {
+                        *count += 1;
+                    }
+                }
+                LaneChangeEvent::Unsafe { direction, .. } => match direction {
+                    LaneChangeDirection::Left => self.unsafe_left += 1,
+                    LaneChangeDirection::Right => self.unsafe_right += 1,
+                },
+            }
+        }
+
+        self.steps += 1;
+        self.vehicle_steps += vehicles.len();
+    }
+
+    pub fn total(&self) -> usize {
+        self.left + self.right
+    }
+
+    pub fn total_unsafe(&self) -> usize {
+        self.unsafe_left + self.unsafe_right
+    }
+
+    /// Average number of lane changes per step
+    pub fn per_step_average(&self) -> f32 {
+        if self.steps == 0 {
+            return 0.0;
+        }
+        self.total() as f32 / self.steps as f32
+    }
+
+    /// Average number of lane changes per vehicle per step
+    pub fn rate(&self) -> f32 {
+        if self.vehicle_steps == 0 {
+            return 0.0;
+        }
+        self.total() as f32 / self.vehicle_steps as f32
+    }
+}
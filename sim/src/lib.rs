@@ -2,11 +2,31 @@ use crate::typedef::Road;
 
 pub mod typedef;
 
+pub mod adaptive_sampling;
+pub mod animation_recorder;
+pub mod batch;
+pub mod calibration;
+pub mod checkpoint;
+pub mod comparison;
+pub mod experiment;
+pub mod gap_histogram;
+pub mod initial_condition;
 pub mod iteration_info;
 pub mod iterations_runner;
+pub mod jam;
+pub mod lane_change;
+pub mod loader;
+pub mod metadata;
+pub mod plot;
+pub mod result_writer;
+pub mod rng;
 pub mod road;
+pub mod sensitivity;
 pub mod simulation_handler;
 pub mod simulation_writer;
+pub mod statistics;
+pub mod summary;
+pub mod trajectory_recorder;
 pub mod vehicle;
 
 // 1. Car checks maximum speed it can achieve on it's current position (x, lane) and adjacent lane (x, lane+1).
@@ -20,6 +40,7 @@ pub mod vehicle;
 /// 2. If the potential maximal speed on lane+1 is higher it checks safe conditions:
 /// 3. Distance to previous car on lane+1 is greater that it's speed to avoid emergency braking of previous car.
 /// 4. Change lane with probability P.
+///
 /// Same steps for lane-1
 /// # Arguments
 /// * `road` - The road to step forward
@@ -27,16 +48,15 @@ pub mod vehicle;
 /// The road after the time step
 /// # Example
 /// ```
-/// use sim::{Road, Vehicle, Position, Velocity};
+/// use sim::typedef::{Road, Vehicle, Position, Velocity};
 /// let road = Road::new(
 ///     100,
 ///     0.0,
 ///     (0..10)
-///         .into_iter()
-///         .map(|x| Vehicle::new(Position::new(x, 0), 0.9, 0.1))
+///         .map(|x| Vehicle::new(x as usize, Position::new(x, 0), None, 0.9, 0.1))
 ///         .collect::<Vec<_>>(),
-///     vec![Velocity::new(30), Velocity::new(30), Velocity::new(30)],
-///     )
+///     vec![Velocity::new(5), Velocity::new(5), Velocity::new(5)],
+/// );
 /// let new_road = sim::step(road);
 /// ```
 pub fn step(mut road: Road) -> Road {
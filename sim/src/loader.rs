@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::simulation_writer::{sibling_file_path, RESULT_EXTENSIONS};
+use crate::typedef::{
+    IterationInfo, LoadError, LoadedResults, MetaData, SampleStatistics, SweepBranch, SweepConfig,
+};
+
+/// The extensions of the files written next to the results, which are not results themselves
+const SIDECAR_EXTENSIONS: [&str; 9] = [
+    "jams",
+    "jam_distribution",
+    "gaps",
+    "lane_changes",
+    "vehicles",
+    "trajectories",
+    "sensitivity",
+    "calibration",
+    "summary",
+];
+
+/// Read the results of a run back from a `.csv` or `.jsonl` file, together with its metadata.
+/// The metadata is read from `<name>.metadata.toml`, or from the legacy `<name>.metadata` free text file.
+/// Columns missing from older results read as zero.
+/// # Arguments
+/// * `file_path` - The path to the results
+pub fn load_results(file_path: &Path) -> Result<LoadedResults, LoadError> {
+    let mut iteration_infos = load_iteration_infos(file_path)?;
+
+    let metadata = metadata_file_path(file_path)
+        .map(|path| MetaData::from_file(&path))
+        .transpose()?;
+
+    if let Some(metadata) = &metadata {
+        for info in &mut iteration_infos {
+            for (_, statistics) in &mut info.metric_statistics {
+                statistics.samples = metadata.experiment.repetitions;
+            }
+        }
+    }
+
+    Ok(LoadedResults {
+        file_path: file_path.to_path_buf(),
+        metadata: metadata.map(|metadata| fill_legacy_probabilities(metadata, &iteration_infos)),
+        iteration_infos,
+    })
+}
+
+/// Read the rows of a `.csv` or `.jsonl` results file
+/// # Arguments
+/// * `file_path` - The path to the results
+pub fn load_iteration_infos(file_path: &Path) -> Result<Vec<IterationInfo>, LoadError> {
+    let rows = match file_path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => read_csv_rows(file_path)?,
+        Some("jsonl") => read_json_lines_rows(file_path)?,
+        _ => {
+            return Err(LoadError::Invalid(format!(
+                "unknown results format: {}",
+                file_path.display()
+            )))
+        }
+    };
+
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            iteration_info(row).map_err(|e| {
+                LoadError::Invalid(format!("{} row {}: {}", file_path.display(), i + 1, e))
+            })
+        })
+        .collect()
+}
+
+/// Every results file below a directory, sorted by path
+/// # Arguments
+/// * `directory` - The directory to search
+pub fn find_results(directory: &Path) -> Result<Vec<PathBuf>, LoadError> {
+    let mut results = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if is_results_file(&path) {
+                results.push(path);
+            }
+        }
+    }
+
+    results.sort();
+    Ok(results)
+}
+
+fn is_results_file(path: &Path) -> bool {
+    let is_results = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| RESULT_EXTENSIONS.contains(&e));
+    let is_sidecar = path
+        .file_stem()
+        .and_then(|stem| Path::new(stem).extension())
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| SIDECAR_EXTENSIONS.contains(&e));
+
+    is_results && !is_sidecar
+}
+
+/// The metadata next to the results, preferring the structured metadata over the legacy free text
+fn metadata_file_path(file_path: &Path) -> Option<PathBuf> {
+    ["metadata.toml", "metadata"]
+        .iter()
+        .map(|extension| sibling_file_path(file_path, extension))
+        .find(|path| path.is_file())
+}
+
+/// Legacy metadata does not record the probabilities that were not swept,
+/// but every row of the results does, so they are taken from the first row
+fn fill_legacy_probabilities(
+    mut metadata: MetaData,
+    iteration_infos: &[IterationInfo],
+) -> MetaData {
+    if metadata.crate_version.is_some() {
+        return metadata;
+    }
+    let Some(first) = iteration_infos.first() else {
+        return metadata;
+    };
+
+    let probabilities = &mut metadata.experiment.probabilities;
+    match metadata.experiment.sweep {
+        SweepConfig::PDecel { .. } => {
+            probabilities.lane_change = first.lane_change_probability;
+        }
+        SweepConfig::PLaneChange { .. } => {
+            probabilities.deceleration = first.deceleration_probability;
+        }
+        _ => {
+            probabilities.deceleration = first.deceleration_probability;
+            probabilities.lane_change = first.lane_change_probability;
+        }
+    }
+    metadata
+}
+
+type Row = HashMap<String, String>;
+
+fn read_csv_rows(file_path: &Path) -> Result<Vec<Row>, LoadError> {
+    Ok(csv::Reader::from_path(file_path)?
+        .deserialize()
+        .collect::<Result<Vec<Row>, _>>()?)
+}
+
+fn read_json_lines_rows(file_path: &Path) -> Result<Vec<Row>, LoadError> {
+    let mut rows = Vec::new();
+    for line in BufReader::new(File::open(file_path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: HashMap<String, serde_json::Value> = serde_json::from_str(&line)?;
+        rows.push(
+            record
+                .into_iter()
+                .map(|(column, value)| {
+                    let value = match value {
+                        serde_json::Value::String(text) => text,
+                        // NaN is written as null
+                        serde_json::Value::Null => "NaN".to_string(),
+                        value => value.to_string(),
+                    };
+                    (column, value)
+                })
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+/// Parse a column of a row, a missing or empty column is the default value
+fn column<T>(row: &Row, name: &str) -> Result<T, String>
+where
+    T: std::str::FromStr + Default,
+    T::Err: std::fmt::Display,
+{
+    match row.get(name).map(|value| value.trim()) {
+        None | Some("") => Ok(T::default()),
+        Some(value) => value
+            .parse()
+            .map_err(|e| format!("invalid {name} '{value}': {e}")),
+    }
+}
+
+/// Parse a column that every results file has
+fn required_column<T>(row: &Row, name: &str) -> Result<T, String>
+where
+    T: std::str::FromStr + Default,
+    T::Err: std::fmt::Display,
+{
+    if !row.contains_key(name) {
+        return Err(format!("missing column {name}"));
+    }
+    column(row, name)
+}
+
+/// Parse the columns of the three lanes, numbered from `first`
+fn lane_columns<T>(row: &Row, prefix: &str, first: usize) -> Result<Vec<T>, String>
+where
+    T: std::str::FromStr + Default,
+    T::Err: std::fmt::Display,
+{
+    (first..first + 3)
+        .map(|lane| column(row, &format!("{prefix}{lane}")))
+        .collect()
+}
+
+fn iteration_info(row: &Row) -> Result<IterationInfo, String> {
+    let branch = match row.get("branch").map(|value| value.trim()) {
+        Some("up") => Some(SweepBranch::Up),
+        Some("down") => Some(SweepBranch::Down),
+        None | Some("") => None,
+        Some(value) => return Err(format!("invalid branch '{value}'")),
+    };
+
+    Ok(IterationInfo {
+        iteration: column(row, "iteration")?,
+        time: Duration::from_secs_f32(column::<f32>(row, "time")?.max(0.0)),
+        average_speed: column(row, "average_speed")?,
+        average_speed_per_lane: lane_columns(row, "average_speed_lane_", 0)?,
+        vehicle_count: column(row, "vehicle_count")?,
+        density: required_column(row, "density")?,
+        lane_change_probability: column(row, "lane_change_probability")?,
+        deceleration_probability: column(row, "deceleration_probability")?,
+        max_speed_per_lane: lane_columns(row, "max_speed_lane_", 1)?,
+        flow: required_column(row, "flow")?,
+        road_length: column(row, "road_length")?,
+        lane_changes_left: column(row, "lane_changes_left")?,
+        lane_changes_right: column(row, "lane_changes_right")?,
+        lane_changes_from_lane: lane_columns(row, "lane_changes_from_lane_", 0)?,
+        unsafe_lane_changes: column(row, "unsafe_lane_changes")?,
+        lane_changes_per_step: column(row, "lane_changes_per_step")?,
+        lane_change_rate: column(row, "lane_change_rate")?,
+        branch,
+        metric_statistics: metric_statistics(row)?,
+        jam_statistics: None,
+        gap_histograms: None,
+        lane_changes_by_step: None,
+        final_vehicles: Vec::new(),
+    })
+}
+
+/// The spread of the metrics, from the `<metric>_std`, `_sem`, `_ci95_low` and `_ci95_high` columns.
+/// The number of samples is not written to the results and is filled in from the metadata.
+fn metric_statistics(row: &Row) -> Result<Vec<(String, SampleStatistics)>, String> {
+    let mut names = row
+        .keys()
+        .filter_map(|column| column.strip_suffix("_ci95_high"))
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    names.sort_by_key(|name| metric_order(name));
+
+    names
+        .into_iter()
+        .map(|name| {
+            let statistics = SampleStatistics {
+                samples: 0,
+                mean: column(row, &name)?,
+                std_dev: column(row, &format!("{name}_std"))?,
+                std_err: column(row, &format!("{name}_sem"))?,
+                ci95_low: column(row, &format!("{name}_ci95_low"))?,
+                ci95_high: column(row, &format!("{name}_ci95_high"))?,
+            };
+            Ok((name, statistics))
+        })
+        .collect()
+}
+
+/// The position of a metric in the results, so a loaded row writes its columns in the original order
+fn metric_order(name: &str) -> (usize, String) {
+    let position = [
+        "average_speed",
+        "average_speed_lane_",
+        "flow",
+        "lane_changes_left",
+        "lane_changes_right",
+        "lane_changes_from_lane_",
+        "unsafe_lane_changes",
+        "lane_changes_per_step",
+        "lane_change_rate",
+    ]
+    .iter()
+    .position(|metric| name == *metric || (metric.ends_with('_') && name.starts_with(metric)))
+    .unwrap_or(usize::MAX);
+
+    (position, name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metric_order_is_the_order_of_the_writer() {
+        let written = [
+            "average_speed",
+            "average_speed_lane_0",
+            "average_speed_lane_1",
+            "flow",
+            "lane_changes_left",
+            "lane_changes_right",
+            "lane_changes_from_lane_0",
+            "unsafe_lane_changes",
+            "lane_changes_per_step",
+            "lane_change_rate",
+        ];
+        let mut loaded = written.to_vec();
+        loaded.sort();
+        loaded.sort_by_key(|name| metric_order(name));
+
+        assert_eq!(loaded, written);
+    }
+
+    #[test]
+    fn legacy_results_take_the_probabilities_from_the_rows() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/probabilities/decel/0.6/535.csv");
+        let results = load_results(&path).unwrap();
+        let metadata = results.metadata.unwrap();
+
+        assert!(metadata.crate_version.is_none());
+        assert_eq!(metadata.experiment.repetitions, 50);
+        assert_eq!(metadata.experiment.iterations, 200);
+        assert_eq!(metadata.experiment.road.length, 100);
+        assert_eq!(metadata.experiment.road.lane_speeds, vec![5, 3, 5]);
+        assert!(matches!(
+            metadata.experiment.sweep,
+            SweepConfig::Density { start, end, .. } if start == 0.01 && end == 0.5
+        ));
+
+        let first = &results.iteration_infos[0];
+        assert_eq!(
+            metadata.experiment.probabilities.deceleration,
+            first.deceleration_probability
+        );
+        assert_eq!(
+            metadata.experiment.probabilities.lane_change,
+            first.lane_change_probability
+        );
+    }
+}
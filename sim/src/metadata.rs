@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::typedef::{
+    ExperimentConfig, ExperimentError, MetaData, OutputConfig, ProbabilityConfig, RoadConfig,
+    SweepConfig,
+};
+
+impl MetaData {
+    /// The metadata of a run that starts now, the wall time and interruption are filled in once it has finished
+    /// # Arguments
+    /// * `experiment` - The resolved experiment of the run
+    pub fn from_experiment(experiment: &ExperimentConfig) -> Self {
+        Self {
+            crate_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            started: Some(chrono::Utc::now().to_rfc3339()),
+            threads: Some(rayon::current_num_threads()),
+            wall_time: None,
+            interrupted: false,
+            seed: experiment.seed,
+            simulation_type: format!("{:?}", experiment.simulation_type()),
+            experiment: experiment.clone(),
+        }
+    }
+
+    /// Read the metadata of a run from a `.toml` or `.json` file, or from a legacy `.metadata` file
+    pub fn from_file(path: &Path) -> Result<Self, ExperimentError> {
+        let contents = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Ok(toml::from_str(&contents)?),
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            Some("metadata") => Self::from_legacy(&contents),
+            other => Err(ExperimentError::UnsupportedFormat(
+                other.unwrap_or_default().to_string(),
+            )),
+        }
+    }
+
+    /// Parse the free text metadata written before the metadata was structured:
+    /// lines of `Key: value`, in newer files followed by `Experiment:` and the resolved experiment as TOML.
+    /// Without the experiment, the probabilities are unknown and take their default values.
+    pub fn from_legacy(text: &str) -> Result<Self, ExperimentError> {
+        let (header, experiment) = match text.split_once("\nExperiment:\n") {
+            Some((header, experiment)) => (header, Some(experiment)),
+            None => (text, None),
+        };
+
+        let fields = header
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .collect::<HashMap<_, _>>();
+        let field = |key: &str| {
+            fields.get(key).map(|value| value.trim()).ok_or_else(|| {
+                ExperimentError::Invalid(format!("the legacy metadata has no '{key}'"))
+            })
+        };
+        let number = |key: &str| {
+            field(key)?.parse::<usize>().map_err(|e| {
+                ExperimentError::Invalid(format!("invalid '{key}' in legacy metadata: {e}"))
+            })
+        };
+
+        let simulation_type = field("Simulation Type")?.to_string();
+        let experiment = match experiment {
+            Some(experiment) => toml::from_str(experiment)?,
+            None => ExperimentConfig {
+                repetitions: number("Number of Simulations")?,
+                iterations: number("Iterations per Simulation")?,
+                seed: None,
+                road: RoadConfig {
+                    length: number("Road Length")? as u8,
+                    lane_speeds: field("Speeds per lane")?
+                        .split_whitespace()
+                        .map(|speed| speed.parse::<u8>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| {
+                            ExperimentError::Invalid(format!(
+                                "invalid 'Speeds per lane' in legacy metadata: {e}"
+                            ))
+                        })?,
+                    ..Default::default()
+                },
+                probabilities: ProbabilityConfig::default(),
+                sweep: legacy_sweep(&simulation_type)?,
+                output: OutputConfig::default(),
+            },
+        };
+
+        Ok(Self {
+            crate_version: None,
+            started: None,
+            threads: None,
+            wall_time: None,
+            interrupted: false,
+            seed: experiment.seed,
+            simulation_type,
+            experiment,
+        })
+    }
+}
+
+/// The sweep described by the simulation type of legacy metadata, like `Density: 0.01 to 0.5 by 0.003333333`
+fn legacy_sweep(simulation_type: &str) -> Result<SweepConfig, ExperimentError> {
+    let invalid = || {
+        ExperimentError::Invalid(format!(
+            "unknown simulation type '{simulation_type}' in legacy metadata"
+        ))
+    };
+
+    let (kind, description) = simulation_type.split_once(": ").ok_or_else(invalid)?;
+    let numbers = description
+        .split_whitespace()
+        .filter_map(|word| word.parse::<f32>().ok())
+        .collect::<Vec<_>>();
+
+    Ok(match (kind, numbers.as_slice()) {
+        ("Density", &[start, end, step]) => SweepConfig::Density { start, end, step },
+        ("Lane Change", &[start, end, step]) => SweepConfig::PLaneChange { start, end, step },
+        ("Deceleration", &[start, end, step]) => SweepConfig::PDecel { start, end, step },
+        ("Hysteresis", &[start, end, step]) => SweepConfig::Hysteresis { start, end, step },
+        ("Lane Speed", &[lane, start, end]) => SweepConfig::LaneSpeed {
+            lane: lane as usize,
+            start: start as u8,
+            end: end as u8,
+        },
+        ("Speed Differential", &[start, end]) => SweepConfig::SpeedDifferential {
+            start: start as u8,
+            end: end as u8,
+        },
+        ("Adaptive Density", &[start, end, budget]) => SweepConfig::AdaptiveDensity {
+            start,
+            end,
+            budget: budget as usize,
+        },
+        _ => return Err(invalid()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_metadata_without_experiment_reads_the_header() {
+        let metadata = MetaData::from_legacy(
+            "Road Length: 100\n\
+             Number of Simulations: 50\n\
+             Iterations per Simulation: 200\n\
+             Simulation Type: Lane Speed: lane 1 from 1 to 10\n\
+             Speeds per lane: 5 3 5\n",
+        )
+        .unwrap();
+
+        assert!(metadata.crate_version.is_none());
+        assert_eq!(metadata.simulation_type, "Lane Speed: lane 1 from 1 to 10");
+        assert_eq!(metadata.experiment.repetitions, 50);
+        assert_eq!(metadata.experiment.road.lane_speeds, vec![5, 3, 5]);
+        assert!(matches!(
+            metadata.experiment.sweep,
+            SweepConfig::LaneSpeed {
+                lane: 1,
+                start: 1,
+                end: 10
+            }
+        ));
+    }
+
+    #[test]
+    fn legacy_metadata_with_experiment_reads_the_experiment() {
+        let metadata = MetaData::from_legacy(
+            "Road Length: 100\n\
+             Simulation Type: Deceleration: 0.1 to 0.9 by 0.1\n\
+             Experiment:\n\
+             seed = 7\n\
+             [sweep]\n\
+             parameter = \"p_decel\"\n\
+             start = 0.1\n\
+             end = 0.9\n\
+             step = 0.1\n",
+        )
+        .unwrap();
+
+        assert_eq!(metadata.seed, Some(7));
+        assert!(matches!(
+            metadata.experiment.sweep,
+            SweepConfig::PDecel { .. }
+        ));
+    }
+
+    #[test]
+    fn legacy_metadata_rejects_an_unknown_simulation_type() {
+        let error = MetaData::from_legacy(
+            "Road Length: 100\n\
+             Number of Simulations: 50\n\
+             Iterations per Simulation: 200\n\
+             Simulation Type: Unknown: 1 to 2\n\
+             Speeds per lane: 5 5 5\n",
+        )
+        .err()
+        .unwrap();
+
+        assert!(error.to_string().contains("unknown simulation type"));
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::typedef::{ExperimentConfig, IterationInfo, LoadedResults, PlotError};
+
+/// The size of a chart in pixels
+const CHART_SIZE: (u32, u32) = (900, 600);
+
+type DrawResult = Result<(), DrawingAreaErrorKind<std::io::Error>>;
+
+/// One run drawn as a line
+struct Series<'a> {
+    label: String,
+    iteration_infos: Vec<&'a IterationInfo>,
+}
+
+/// Draw the fundamental diagrams of one or more runs as SVG, overlaying the runs with a legend:
+/// the flow over the density to `<output>.flow.svg`, the average speed over the density to `<output>.speed.svg`
+/// and the average speed of every lane over the density to `<output>.lane_speed.svg`.
+/// The runs are labeled by the parameters in which their metadata differ, or by their file name.
+/// # Arguments
+/// * `results` - The runs to draw
+/// * `output` - The path of the charts without the extension
+/// * `title` - The title above the charts
+/// # Returns
+/// The paths of the charts that were written
+pub fn plot_fundamental_diagrams(
+    results: &[LoadedResults],
+    output: &Path,
+    title: Option<&str>,
+) -> Result<Vec<PathBuf>, PlotError> {
+    if results.iter().all(|r| r.iteration_infos.is_empty()) {
+        return Err(PlotError::Empty("the results contain no rows".to_string()));
+    }
+
+    let labels = series_labels(results);
+    let series = results
+        .iter()
+        .zip(labels)
+        .map(|(result, label)| {
+            let mut iteration_infos = result.iteration_infos.iter().collect::<Vec<_>>();
+            // A hysteresis sweep is drawn in the order it was run, up and back down the density
+            if iteration_infos.iter().all(|info| info.branch.is_none()) {
+                iteration_infos.sort_by(|a, b| a.density.total_cmp(&b.density));
+            }
+            Series {
+                label,
+                iteration_infos,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let chart_path = |name: &str| {
+        let mut file_name = output.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{name}.svg"));
+        output.with_file_name(file_name)
+    };
+    if let Some(directory) = output.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(directory)?;
+    }
+    let flow_path = chart_path("flow");
+    let speed_path = chart_path("speed");
+    let lane_speed_path = chart_path("lane_speed");
+
+    let drawn = draw_single_chart(
+        &flow_path,
+        title.unwrap_or("Flow"),
+        "Flow (vehicles per cell and step)",
+        &series,
+        |info| Some(info.flow),
+    )
+    .and_then(|_| {
+        draw_single_chart(
+            &speed_path,
+            title.unwrap_or("Average speed"),
+            "Average speed (cells per step)",
+            &series,
+            |info| Some(info.average_speed),
+        )
+    })
+    .and_then(|_| draw_lane_speed_charts(&lane_speed_path, title, &series));
+    drawn.map_err(|e| PlotError::Drawing(e.to_string()))?;
+
+    Ok(vec![flow_path, speed_path, lane_speed_path])
+}
+
+/// Draw one metric of every run over the density to its own file
+fn draw_single_chart(
+    file_path: &Path,
+    caption: &str,
+    y_description: &str,
+    series: &[Series],
+    metric: impl Fn(&IterationInfo) -> Option<f32>,
+) -> DrawResult {
+    let root = SVGBackend::new(file_path, CHART_SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    draw_chart(&root, caption, y_description, series, metric)?;
+    root.present()
+}
+
+/// The average speed of every lane next to each other.
+/// An empty lane is written with an average speed of zero, so points where a lane has no speed are left out.
+fn draw_lane_speed_charts(file_path: &Path, title: Option<&str>, series: &[Series]) -> DrawResult {
+    let root =
+        SVGBackend::new(file_path, (CHART_SIZE.0 * 2, CHART_SIZE.1 * 2 / 3)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let root = match title {
+        Some(title) => root.titled(title, ("sans-serif", 24))?,
+        None => root,
+    };
+
+    for (lane, area) in root.split_evenly((1, 3)).iter().enumerate() {
+        draw_chart(
+            area,
+            &format!("Lane {}", lane + 1),
+            "Average speed (cells per step)",
+            series,
+            |info| Some(info.average_speed_per_lane[lane]).filter(|speed| *speed > 0.0),
+        )?;
+    }
+    root.present()
+}
+
+/// Draw one metric of every run over the density, leaving out the points without a value
+fn draw_chart(
+    area: &DrawingArea<SVGBackend, Shift>,
+    caption: &str,
+    y_description: &str,
+    series: &[Series],
+    metric: impl Fn(&IterationInfo) -> Option<f32>,
+) -> DrawResult {
+    let points = |s: &Series| {
+        s.iteration_infos
+            .iter()
+            .filter_map(|info| metric(info).map(|y| (info.density, y)))
+            .filter(|(_, y)| y.is_finite())
+            .collect::<Vec<_>>()
+    };
+    let (x_max, y_max) = series
+        .iter()
+        .flat_map(points)
+        .fold((f32::EPSILON, f32::EPSILON), |(x_max, y_max), (x, y)| {
+            (x_max.max(x), y_max.max(y))
+        });
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, ("sans-serif", 22))
+        .margin(15)
+        .x_label_area_size(45)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0f32..x_max * 1.05, 0f32..y_max * 1.1)?;
+    chart
+        .configure_mesh()
+        .x_desc("Density (vehicles per cell)")
+        .y_desc(y_description)
+        .draw()?;
+
+    for (i, s) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        chart
+            .draw_series(LineSeries::new(points(s), color.stroke_width(2)))?
+            .label(&s.label)
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    Ok(())
+}
+
+/// Label every run by the parameters in which its metadata differ from the other runs.
+/// Runs without metadata, or with the same parameters, are labeled by their file name.
+fn series_labels(results: &[LoadedResults]) -> Vec<String> {
+    let file_names = results
+        .iter()
+        .map(|r| {
+            r.file_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let Some(parameters) = results
+        .iter()
+        .map(|r| r.metadata.as_ref().map(|m| run_parameters(&m.experiment)))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return file_names;
+    };
+
+    let differing = (0..parameters[0].len())
+        .filter(|&i| parameters.iter().any(|p| p[i] != parameters[0][i]))
+        .collect::<Vec<_>>();
+    if results.len() < 2 || differing.is_empty() {
+        return file_names;
+    }
+
+    parameters
+        .iter()
+        .map(|p| {
+            differing
+                .iter()
+                .map(|&i| format!("{} {}", p[i].0, p[i].1))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect()
+}
+
+/// The parameters of a run that tell it apart from other runs
+fn run_parameters(experiment: &ExperimentConfig) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "lane speeds",
+            experiment
+                .road
+                .lane_speeds
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        ("p_decel", experiment.probabilities.deceleration.to_string()),
+        (
+            "p_lane_change",
+            experiment.probabilities.lane_change.to_string(),
+        ),
+        ("road length", experiment.road.length.to_string()),
+        ("simulations", experiment.repetitions.to_string()),
+        ("iterations", experiment.iterations.to_string()),
+    ]
+}
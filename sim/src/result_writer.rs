@@ -0,0 +1,216 @@
+use std::fmt;
+use std::io::Write;
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::simulation_writer::nan_to_zero;
+use crate::typedef::{
+    CsvResultWriter, IterationInfo, JsonLinesResultWriter, ResultWriter, SweepBranch, WriterError,
+};
+
+/// The columns of the results in front of the spread of the metrics
+const COLUMNS: [&str; 24] = [
+    "iteration",
+    "time",
+    "density",
+    "average_speed",
+    "average_speed_lane_0",
+    "average_speed_lane_1",
+    "average_speed_lane_2",
+    "lane_change_probability",
+    "deceleration_probability",
+    "max_speed_lane_1",
+    "max_speed_lane_2",
+    "max_speed_lane_3",
+    "flow",
+    "vehicle_count",
+    "lane_changes_left",
+    "lane_changes_right",
+    "lane_changes_from_lane_0",
+    "lane_changes_from_lane_1",
+    "lane_changes_from_lane_2",
+    "unsafe_lane_changes",
+    "lane_changes_per_step",
+    "lane_change_rate",
+    "branch",
+    "road_length",
+];
+
+/// One value in a row of the results
+enum ResultValue {
+    Float(f32),
+    Integer(usize),
+    Text(&'static str),
+}
+
+impl From<f32> for ResultValue {
+    fn from(value: f32) -> Self {
+        ResultValue::Float(value)
+    }
+}
+
+impl From<usize> for ResultValue {
+    fn from(value: usize) -> Self {
+        ResultValue::Integer(value)
+    }
+}
+
+impl From<u8> for ResultValue {
+    fn from(value: u8) -> Self {
+        ResultValue::Integer(value as usize)
+    }
+}
+
+impl fmt::Display for ResultValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResultValue::Float(value) => write!(f, "{value}"),
+            ResultValue::Integer(value) => write!(f, "{value}"),
+            ResultValue::Text(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl Serialize for ResultValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            ResultValue::Float(value) => serializer.serialize_f32(value),
+            ResultValue::Integer(value) => serializer.serialize_u64(value as u64),
+            ResultValue::Text(value) => serializer.serialize_str(value),
+        }
+    }
+}
+
+/// A row of the results as a map from column name to value
+struct ResultRecord<'a> {
+    columns: &'a [String],
+    values: &'a [ResultValue],
+}
+
+impl Serialize for ResultRecord<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for (column, value) in self.columns.iter().zip(self.values) {
+            map.serialize_entry(column, value)?;
+        }
+        map.end()
+    }
+}
+
+/// The names of the columns of the results
+/// # Arguments
+/// * `metric_names` - The metrics for which the spread over the simulations is written
+pub fn result_columns(metric_names: &[String]) -> Vec<String> {
+    let mut columns = COLUMNS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    for name in metric_names {
+        columns.extend(
+            ["std", "sem", "ci95_low", "ci95_high"]
+                .iter()
+                .map(|statistic| format!("{name}_{statistic}")),
+        );
+    }
+    columns
+}
+
+/// The values of a row of the results, in the order of `result_columns`
+fn result_values(i_inf: &IterationInfo) -> Vec<ResultValue> {
+    let mut values: Vec<ResultValue> = vec![
+        i_inf.iteration.into(),
+        i_inf.time.as_secs_f32().into(),
+        i_inf.density.into(),
+        nan_to_zero(i_inf.average_speed).into(),
+        nan_to_zero(i_inf.average_speed_per_lane[0]).into(),
+        nan_to_zero(i_inf.average_speed_per_lane[1]).into(),
+        nan_to_zero(i_inf.average_speed_per_lane[2]).into(),
+        i_inf.lane_change_probability.into(),
+        i_inf.deceleration_probability.into(),
+        i_inf.max_speed_per_lane[0].into(),
+        i_inf.max_speed_per_lane[1].into(),
+        i_inf.max_speed_per_lane[2].into(),
+        i_inf.flow.into(),
+        i_inf.vehicle_count.into(),
+        i_inf.lane_changes_left.into(),
+        i_inf.lane_changes_right.into(),
+        i_inf.lane_changes_from_lane[0].into(),
+        i_inf.lane_changes_from_lane[1].into(),
+        i_inf.lane_changes_from_lane[2].into(),
+        i_inf.unsafe_lane_changes.into(),
+        i_inf.lane_changes_per_step.into(),
+        i_inf.lane_change_rate.into(),
+        ResultValue::Text(match i_inf.branch {
+            Some(SweepBranch::Up) => "up",
+            Some(SweepBranch::Down) => "down",
+            None => "",
+        }),
+        i_inf.road_length.into(),
+    ];
+    for (_, statistics) in &i_inf.metric_statistics {
+        values.extend([
+            statistics.std_dev.into(),
+            statistics.std_err.into(),
+            statistics.ci95_low.into(),
+            statistics.ci95_high.into(),
+        ]);
+    }
+    values
+}
+
+impl<W: Write> CsvResultWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+}
+
+impl<W: Write> ResultWriter for CsvResultWriter<W> {
+    fn write_header(&mut self, metric_names: &[String]) -> Result<(), WriterError> {
+        self.writer.write_record(result_columns(metric_names))?;
+        Ok(())
+    }
+
+    fn write_row(&mut self, iteration_info: &IterationInfo) -> Result<(), WriterError> {
+        self.writer.write_record(
+            result_values(iteration_info)
+                .iter()
+                .map(|value| value.to_string()),
+        )?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> JsonLinesResultWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            columns: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> ResultWriter for JsonLinesResultWriter<W> {
+    fn write_header(&mut self, metric_names: &[String]) -> Result<(), WriterError> {
+        self.columns = result_columns(metric_names);
+        Ok(())
+    }
+
+    fn write_row(&mut self, iteration_info: &IterationInfo) -> Result<(), WriterError> {
+        let record = ResultRecord {
+            columns: &self.columns,
+            values: &result_values(iteration_info),
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WriterError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
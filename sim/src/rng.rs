@@ -0,0 +1,27 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Derive an independent seed from a seed and a number of stream identifiers,
+/// so every vehicle, step and simulation can get its own reproducible random numbers
+/// regardless of the order in which they are run.
+pub fn derive_seed(seed: u64, streams: &[u64]) -> u64 {
+    streams.iter().fold(splitmix64(seed), |acc, stream| {
+        splitmix64(acc ^ splitmix64(*stream))
+    })
+}
+
+/// Choose a random seed. TOML integers are signed, so the seed is kept within 63 bits.
+pub fn random_seed() -> u64 {
+    rand::random::<u64>() >> 1
+}
+
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
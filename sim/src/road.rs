@@ -1,8 +1,19 @@
-use crate::typedef::{Position, Road, Vehicle, Velocity};
+use crate::rng::{derive_seed, seeded_rng};
+use crate::typedef::{
+    InitialCondition, LaneChangeCounts, Position, Road, RoadStateError, StateFormat, Vehicle,
+    VehicleStatistics, Velocity,
+};
+use colored::{Color, Colorize};
+use rand::Rng;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use std::cmp::min;
-use std::io::{stdout, Write};
-use colored::Colorize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{stdout, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The colour of a vehicle by the lane it started in, and of the maximum speed of that lane
+pub const LANE_COLORS: [Color; 3] = [Color::Blue, Color::Green, Color::Red];
 
 impl Road {
     pub fn new(
@@ -15,7 +26,10 @@ impl Road {
             len,
             deceleration_probability,
             vehicles,
+            lane_change_counts: LaneChangeCounts::new(speed_per_lane.len()),
             speed_per_lane,
+            seed: rand::random(),
+            steps: 0,
         }
     }
 
@@ -34,8 +48,137 @@ impl Road {
             .vehicles
             .clone()
             .into_par_iter()
-            .map(|vehicle| vehicle.update(&self))
+            .map(|vehicle| vehicle.update(self))
             .collect::<Vec<_>>();
+
+        self.lane_change_counts.record_step(&self.vehicles);
+        self.steps += 1;
+    }
+
+    /// The number of vehicles needed to reach a density, rounded down like in `create_road`
+    pub fn vehicles_for_density(&self, density: f32) -> usize {
+        (self.len as f32 * density * self.speed_per_lane.len() as f32) as usize
+    }
+
+    /// Add or remove vehicles until the road has the given density.
+    /// New vehicles are placed standing still on random empty cells, removed vehicles are chosen at random.
+    /// # Arguments
+    /// * `density` - The density to reach
+    /// * `lane_change_probability` - The lane change probability of new vehicles
+    pub fn set_density(&mut self, density: f32, lane_change_probability: f32) {
+        self.set_vehicle_count(self.vehicles_for_density(density), lane_change_probability);
+    }
+
+    /// Add or remove vehicles until the road has the given number of vehicles, at most one per cell.
+    /// New vehicles are placed standing still on random empty cells, removed vehicles are chosen at random.
+    /// # Arguments
+    /// * `count` - The number of vehicles to reach
+    /// * `lane_change_probability` - The lane change probability of new vehicles
+    pub fn set_vehicle_count(&mut self, count: usize, lane_change_probability: f32) {
+        let mut rng = seeded_rng(derive_seed(self.seed, &[self.steps, u64::MAX]));
+        let lanes = self.speed_per_lane.len() as u8;
+        let target = count.min(self.len as usize * lanes as usize);
+
+        while self.vehicles.len() > target {
+            let idx = rng.gen_range(0..self.vehicles.len());
+            self.vehicles.swap_remove(idx);
+        }
+
+        let mut next_id = self.vehicles.iter().map(|v| v.id + 1).max().unwrap_or(0);
+        while self.vehicles.len() < target {
+            let position = Position::new(rng.gen_range(0..self.len), rng.gen_range(0..lanes));
+            if self.vehicles.iter().any(|v| v.position == position) {
+                continue;
+            }
+
+            self.vehicles.push(Vehicle::new(
+                next_id,
+                position,
+                None,
+                lane_change_probability,
+                lane_change_probability,
+            ));
+            next_id += 1;
+        }
+    }
+
+    /// Set the chance of every vehicle to change lanes when it wants to
+    pub fn set_lane_change_probability(&mut self, lane_change_probability: f32) {
+        for vehicle in self.vehicles.iter_mut() {
+            vehicle.move_left_chance = lane_change_probability;
+            vehicle.move_right_chance = lane_change_probability;
+        }
+    }
+
+    /// Save the full state of the road, so a simulation can be continued from it exactly
+    /// # Arguments
+    /// * `file_path` - The file to write the state to
+    /// * `format` - The encoding of the state
+    pub fn save_state(&self, file_path: &Path, format: StateFormat) -> Result<(), RoadStateError> {
+        let mut writer = BufWriter::new(fs::File::create(file_path)?);
+        match format {
+            StateFormat::Json => serde_json::to_writer_pretty(&mut writer, self)?,
+            StateFormat::Binary => bincode::serialize_into(&mut writer, self)?,
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Load a road saved with `save_state`.
+    /// Files ending in `.json` are read as JSON, all other files as binary.
+    pub fn load_state(file_path: &Path) -> Result<Self, RoadStateError> {
+        let reader = BufReader::new(fs::File::open(file_path)?);
+        let road: Road = match file_path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_reader(reader)?,
+            _ => bincode::deserialize_from(reader)?,
+        };
+
+        if road.speed_per_lane.len() != 3 {
+            return Err(RoadStateError::Invalid(format!(
+                "expected 3 lanes, got {}",
+                road.speed_per_lane.len()
+            )));
+        }
+        if road.vehicles.is_empty() {
+            return Err(RoadStateError::Invalid(
+                "the road has no vehicles".to_string(),
+            ));
+        }
+        let mut ids = HashSet::new();
+        for vehicle in &road.vehicles {
+            if vehicle.position.x >= road.len || vehicle.position.y >= 3 {
+                return Err(RoadStateError::Invalid(format!(
+                    "vehicle {} at {:?} is not on the road",
+                    vehicle.id, vehicle.position
+                )));
+            }
+            if vehicle.original_lane >= 3 {
+                return Err(RoadStateError::Invalid(format!(
+                    "vehicle {} started in lane {}, which is not on the road",
+                    vehicle.id, vehicle.original_lane
+                )));
+            }
+            // Vehicles do not brake for the vehicle in front, so two vehicles can share a cell,
+            // but every vehicle must keep its own id for its statistics and trajectory
+            if !ids.insert(vehicle.id) {
+                return Err(RoadStateError::Invalid(format!(
+                    "vehicle id {} is used by more than one vehicle",
+                    vehicle.id
+                )));
+            }
+        }
+
+        Ok(road)
+    }
+
+    /// Reset the lane change counts and the statistics of every vehicle,
+    /// so a road can be measured again from its current state
+    pub fn reset_statistics(&mut self) {
+        self.lane_change_counts = LaneChangeCounts::new(self.speed_per_lane.len());
+        for vehicle in self.vehicles.iter_mut() {
+            vehicle.statistics = VehicleStatistics::default();
+        }
     }
 
     pub fn get_average_speed(&self) -> f32 {
@@ -56,7 +199,6 @@ impl Road {
 
     pub fn get_average_speed_per_lane(&self) -> Vec<f32> {
         (0..3)
-            .into_iter()
             .map(|lane| {
                 let vs = self
                     .vehicles
@@ -78,6 +220,33 @@ impl Road {
             .collect::<Vec<_>>()
     }
 
+    /// Get the vehicles in a lane ordered by their x position, together with the
+    /// number of empty cells between each vehicle and the vehicle in front of it.
+    /// Like in `distance_to_next_vehicle`, vehicles on the same cell are not seen as the vehicle in front,
+    /// and a vehicle without any vehicle in front of it has a gap of `u8::MAX`.
+    pub fn get_gaps_in_lane(&self, lane: u8) -> Vec<(&Vehicle, u8)> {
+        let mut vehicles_in_lane = self.get_vehicles_in_lane(lane);
+        vehicles_in_lane.sort_by_key(|v| v.position.x);
+
+        vehicles_in_lane
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| {
+                let next_vehicle = (1..vehicles_in_lane.len())
+                    .map(|offset| vehicles_in_lane[(idx + offset) % vehicles_in_lane.len()])
+                    .find(|next| next.position.x != v.position.x);
+
+                match next_vehicle {
+                    Some(next) => (
+                        *v,
+                        self.dist_between_vehicles(next.position.x, v.position.x),
+                    ),
+                    None => (*v, u8::MAX),
+                }
+            })
+            .collect()
+    }
+
     pub fn get_vehicles_in_lane_mut(&mut self, lane: u8) -> Vec<&mut Vehicle> {
         self.vehicles
             .iter_mut()
@@ -162,7 +331,6 @@ impl Road {
 
     pub fn pretty_print_lane(&self, lane: u8, strides: bool) -> String {
         (0..self.len)
-            .into_iter()
             .map(|f| {
                 match self
                     .vehicles
@@ -171,15 +339,8 @@ impl Road {
                 {
                     Some(v) => {
                         let text = v.velocity.into_inner().to_string();
-                        let color = match v.original_lane {
-                            0 => text.blue(),
-                            1 => text.green(),
-                            2 => text.red(),
-                            _ => unreachable!(),
-                        };
-
-                        format!("{color}")
-                    },
+                        format!("{}", text.color(LANE_COLORS[v.original_lane as usize]))
+                    }
                     None => " ".to_string(),
                 }
             })
@@ -205,13 +366,34 @@ impl Road {
 
         const SIDE_OF_ROAD_STR: &str = "#";
 
-        let s = vec![
+        let s = [
             SIDE_OF_ROAD_STR.repeat(self.len as usize),
-            format!("{}\t{}", self.pretty_print_lane(2, false), self.speed_per_lane[2].into_inner().to_string().red()),
+            format!(
+                "{}\t{}",
+                self.pretty_print_lane(2, false),
+                self.speed_per_lane[2]
+                    .into_inner()
+                    .to_string()
+                    .color(LANE_COLORS[2])
+            ),
             self.get_strides(),
-            format!("{}\t{}", self.pretty_print_lane(1, false), self.speed_per_lane[1].into_inner().to_string().green()),
+            format!(
+                "{}\t{}",
+                self.pretty_print_lane(1, false),
+                self.speed_per_lane[1]
+                    .into_inner()
+                    .to_string()
+                    .color(LANE_COLORS[1])
+            ),
             self.get_strides(),
-            format!("{}\t{}", self.pretty_print_lane(0, false), self.speed_per_lane[0].into_inner().to_string().blue()),
+            format!(
+                "{}\t{}",
+                self.pretty_print_lane(0, false),
+                self.speed_per_lane[0]
+                    .into_inner()
+                    .to_string()
+                    .color(LANE_COLORS[0])
+            ),
             SIDE_OF_ROAD_STR.repeat(self.len as usize),
         ]
         .join("\n");
@@ -257,16 +439,17 @@ impl Road {
 }
 
 /// Create a new road
+/// All random numbers of the road, both for the initial positions and for every step, are derived from `seed`
 pub fn create_road(
     length: usize,
     density: f32,
     mut speed_per_lane: Vec<u8>,
     deceleration_probability: f32,
     lange_change_probability: f32,
-    random_car_start_pos: bool,
-    random_car_start_speed: bool,
+    initial_condition: &InitialCondition,
+    seed: u64,
 ) -> Road {
-    let amount_of_cars = (length as f32 * density * 3.0) as usize;
+    let mut rng = seeded_rng(seed);
 
     if speed_per_lane.is_empty() {
         print!("No speeds provided, defaulting to 5 for all lanes");
@@ -280,30 +463,42 @@ pub fn create_road(
     //Remove any extra speeds
     speed_per_lane.truncate(3);
 
+    let placement = &initial_condition.placement;
+    let lanes_of_vehicles = placement.lanes_of_vehicles(length, density, 3);
+    let mut vehicles_per_lane = [0; 3];
+    for lane in &lanes_of_vehicles {
+        vehicles_per_lane[*lane] += 1;
+    }
+    let mut placed_per_lane = [0; 3];
+
     let mut vehicles = Vec::new();
 
-    for i in 0..amount_of_cars {
-        let mut lane = i % 3;
-        let mut x = (i / 3) as u8;
-        if random_car_start_pos {
-            lane = rand::random::<usize>() % 3;
-            x = rand::random::<u8>() % length as u8;
-            while vehicles
-                .iter()
-                .any(|v: &Vehicle| v.position.x == x && v.position.y == lane as u8)
-            {
-                lane = rand::random::<usize>() % 3;
-                x = rand::random::<u8>() % length as u8;
+    for (i, mut lane) in lanes_of_vehicles.into_iter().enumerate() {
+        let mut x = placement.fixed_cell(placed_per_lane[lane], vehicles_per_lane[lane], length);
+        placed_per_lane[lane] += 1;
+
+        if placement.random_cell() {
+            loop {
+                if placement.random_lane() {
+                    lane = rng.gen_range(0..3);
+                }
+                x = rng.gen_range(0..length as u8);
+
+                if !vehicles
+                    .iter()
+                    .any(|v: &Vehicle| v.position.x == x && v.position.y == lane as u8)
+                {
+                    break;
+                }
             }
         }
 
-        let speed = if random_car_start_speed {
-            Velocity::new(rand::random::<u8>() % speed_per_lane[lane] + 1)
-        } else {
-            Velocity::new(0)
-        };
+        let speed = initial_condition
+            .speeds
+            .sample(speed_per_lane[lane], &mut rng);
 
         vehicles.push(Vehicle::new(
+            i,
             Position::new(x, lane as u8),
             Some(speed),
             lange_change_probability,
@@ -311,10 +506,22 @@ pub fn create_road(
         ));
     }
 
-    Road::new(
+    let mut road = Road::new(
         length as u8,
         deceleration_probability,
         vehicles,
         speed_per_lane.into_iter().map(Velocity::new).collect(),
-    )
+    );
+    road.seed = rng.gen();
+    road
+}
+
+impl StateFormat {
+    /// The extension of files in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            StateFormat::Json => "json",
+            StateFormat::Binary => "bin",
+        }
+    }
 }
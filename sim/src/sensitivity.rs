@@ -0,0 +1,276 @@
+use rand::Rng;
+
+use crate::{
+    rng::{derive_seed, seeded_rng},
+    simulation_writer::nan_to_zero,
+    typedef::{
+        IterationInfo, ParameterPoint, SensitivityDesign, SensitivityFactor, SensitivityIndex,
+    },
+};
+
+/// The random stream of the sample design, apart from the streams of the simulations
+const DESIGN_STREAM: u64 = u64::MAX;
+/// The number of bootstrap resamples behind the confidence intervals of the indices
+const BOOTSTRAP_RESAMPLES: usize = 500;
+
+/// Reads one output of the model from the averaged results of a parameter set
+type Output = fn(&IterationInfo) -> f32;
+
+/// The analysed outputs of the model and their names
+const OUTPUTS: [(&str, Output); 2] = [
+    ("flow", |info| info.flow),
+    ("average_speed", |info| nan_to_zero(info.average_speed)),
+];
+
+impl SensitivityFactor {
+    /// The name of the factor, as in the experiment file
+    pub fn name(&self) -> String {
+        match self {
+            SensitivityFactor::Density(..) => "density".to_string(),
+            SensitivityFactor::DecelerationProbability(..) => "p_decel".to_string(),
+            SensitivityFactor::LaneChangeProbability(..) => "p_lane_change".to_string(),
+            SensitivityFactor::LaneSpeed(lane, ..) => format!("lane_speed_{lane}"),
+        }
+    }
+
+    pub fn bounds(&self) -> (f32, f32) {
+        match *self {
+            SensitivityFactor::Density(low, high)
+            | SensitivityFactor::DecelerationProbability(low, high)
+            | SensitivityFactor::LaneChangeProbability(low, high) => (low, high),
+            SensitivityFactor::LaneSpeed(_, low, high) => (low as f32, high as f32),
+        }
+    }
+
+    /// Set the factor of a parameter set to the value at `u` between its bounds
+    /// # Arguments
+    /// * `point` - The parameter set to change
+    /// * `u` - The position between the bounds, from 0 up to but excluding 1
+    fn apply(&self, point: &mut ParameterPoint, u: f32) {
+        let (low, high) = self.bounds();
+        match *self {
+            SensitivityFactor::Density(..) => point.density = low + u * (high - low),
+            SensitivityFactor::DecelerationProbability(..) => {
+                point.deceleration_probability = low + u * (high - low)
+            }
+            SensitivityFactor::LaneChangeProbability(..) => {
+                point.lane_change_probability = low + u * (high - low)
+            }
+            SensitivityFactor::LaneSpeed(lane, low, high) => {
+                let speeds = (high - low) as f32 + 1.0;
+                point.lane_speeds[lane] = ((low as f32 + u * speeds) as u8).min(high)
+            }
+        }
+    }
+}
+
+impl SensitivityDesign {
+    /// The number of parameter sets simulated by the analysis
+    pub fn point_count(&self) -> usize {
+        self.samples * (self.factors.len() + 2)
+    }
+
+    /// The parameter sets of the analysis, in the order in which they are simulated.
+    /// Every base sample gives the sets A and B, followed by A with the first, second, ... factor taken from B.
+    /// # Arguments
+    /// * `base` - The values of the parameters that are not varied
+    /// * `seed` - The seed of the experiment
+    pub fn points(&self, base: &ParameterPoint, seed: u64) -> Vec<ParameterPoint> {
+        let mut rng = seeded_rng(derive_seed(seed, &[DESIGN_STREAM]));
+        let factors = self.factors.len();
+        let point = |u: &[f32]| {
+            let mut point = base.clone();
+            for (factor, u) in self.factors.iter().zip(u) {
+                factor.apply(&mut point, *u);
+            }
+            point
+        };
+
+        let mut points = Vec::with_capacity(self.point_count());
+        for _ in 0..self.samples {
+            let a = (0..factors).map(|_| rng.gen::<f32>()).collect::<Vec<_>>();
+            let b = (0..factors).map(|_| rng.gen::<f32>()).collect::<Vec<_>>();
+            points.push(point(&a));
+            points.push(point(&b));
+            for factor in 0..factors {
+                let mut ab = a.clone();
+                ab[factor] = b[factor];
+                points.push(point(&ab));
+            }
+        }
+        points
+    }
+
+    /// The first and total order indices of every factor for the flow and the average speed.
+    /// Only base samples of which every parameter set was simulated are used,
+    /// so an interrupted analysis gives the indices of fewer samples.
+    /// # Arguments
+    /// * `iteration_infos` - The averaged results of the parameter sets, in the order of `points`
+    /// * `seed` - The seed of the experiment, used for the bootstrap resamples
+    /// # Returns
+    /// The indices, or nothing if fewer than two base samples were completed.
+    /// The indices of an output that does not vary at all are NaN.
+    pub fn indices(&self, iteration_infos: &[IterationInfo], seed: u64) -> Vec<SensitivityIndex> {
+        let block = self.factors.len() + 2;
+        let samples = iteration_infos.len() / block;
+        if samples < 2 {
+            return Vec::new();
+        }
+
+        let mut rng = seeded_rng(derive_seed(seed, &[DESIGN_STREAM, 1]));
+        let resamples = (0..BOOTSTRAP_RESAMPLES)
+            .map(|_| {
+                (0..samples)
+                    .map(|_| rng.gen_range(0..samples))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let all_samples = (0..samples).collect::<Vec<_>>();
+
+        let mut indices = Vec::new();
+        for (output, value) in OUTPUTS {
+            let values = iteration_infos[..samples * block]
+                .iter()
+                .map(value)
+                .collect::<Vec<_>>();
+            let blocks = values.chunks(block).collect::<Vec<_>>();
+
+            for (factor_index, factor) in self.factors.iter().enumerate() {
+                let (first_order, total_order) = sobol_indices(&blocks, &all_samples, factor_index);
+                let (first_orders, total_orders): (Vec<_>, Vec<_>) = resamples
+                    .iter()
+                    .map(|resample| sobol_indices(&blocks, resample, factor_index))
+                    .unzip();
+
+                indices.push(SensitivityIndex {
+                    output,
+                    factor: factor.name(),
+                    first_order,
+                    first_order_ci95: percentile_interval(first_orders),
+                    total_order,
+                    total_order_ci95: percentile_interval(total_orders),
+                });
+            }
+        }
+        indices
+    }
+}
+
+/// The first order index with the estimator of Saltelli et al. (2010)
+/// and the total order index with the estimator of Jansen (1999)
+/// # Arguments
+/// * `blocks` - The outputs of every base sample, in the order A, B, A with factor 0 from B, ...
+/// * `samples` - The base samples to use, a sample may appear more than once
+/// * `factor` - The index of the factor
+fn sobol_indices(blocks: &[&[f32]], samples: &[usize], factor: usize) -> (f32, f32) {
+    let n = samples.len() as f64;
+    let output = |sample: usize, column: usize| blocks[sample][column] as f64;
+
+    // The mean and variance of the output over the sets A and B together
+    let mean = samples
+        .iter()
+        .map(|&sample| output(sample, 0) + output(sample, 1))
+        .sum::<f64>()
+        / (2.0 * n);
+    let variance = samples
+        .iter()
+        .map(|&sample| (output(sample, 0) - mean).powi(2) + (output(sample, 1) - mean).powi(2))
+        .sum::<f64>()
+        / (2.0 * n);
+    if variance <= f64::EPSILON {
+        return (f32::NAN, f32::NAN);
+    }
+
+    let (mut first, mut total) = (0.0, 0.0);
+    for &sample in samples {
+        let (a, b, ab) = (
+            output(sample, 0),
+            output(sample, 1),
+            output(sample, 2 + factor),
+        );
+        // Centering B does not change the expectation, but makes the estimate less noisy
+        first += (b - mean) * (ab - a);
+        total += (a - ab).powi(2);
+    }
+
+    (
+        (first / n / variance) as f32,
+        (total / (2.0 * n) / variance) as f32,
+    )
+}
+
+/// The 2.5th and 97.5th percentile of bootstrap estimates
+fn percentile_interval(mut estimates: Vec<f32>) -> (f32, f32) {
+    estimates.retain(|estimate| estimate.is_finite());
+    if estimates.is_empty() {
+        return (f32::NAN, f32::NAN);
+    }
+    estimates.sort_by(f32::total_cmp);
+
+    let at = |fraction: f32| estimates[(fraction * (estimates.len() - 1) as f32).round() as usize];
+    (at(0.025), at(0.975))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The outputs of a linear model `x1 + 2 x2` of two uniform inputs, in the order of `SensitivityDesign::points`
+    fn linear_model_blocks(samples: usize) -> Vec<[f32; 4]> {
+        let model = |x: [f32; 2]| x[0] + 2.0 * x[1];
+        let mut rng = seeded_rng(7);
+        (0..samples)
+            .map(|_| {
+                let a = [rng.gen::<f32>(), rng.gen::<f32>()];
+                let b = [rng.gen::<f32>(), rng.gen::<f32>()];
+                [model(a), model(b), model([b[0], a[1]]), model([a[0], b[1]])]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sobol_indices_of_a_linear_model() {
+        let blocks = linear_model_blocks(20_000);
+        let blocks = blocks.iter().map(|b| &b[..]).collect::<Vec<_>>();
+        let samples = (0..blocks.len()).collect::<Vec<_>>();
+
+        // The variance of a * x of a uniform x is a² / 12, so the factors explain 1 / 5 and 4 / 5 of the variance.
+        // Without interactions the total order indices equal the first order indices.
+        for (factor, expected) in [(0, 0.2), (1, 0.8)] {
+            let (first_order, total_order) = sobol_indices(&blocks, &samples, factor);
+            assert!(
+                (first_order - expected).abs() < 0.02,
+                "first order index of factor {factor} is {first_order}, expected {expected}"
+            );
+            assert!(
+                (total_order - expected).abs() < 0.02,
+                "total order index of factor {factor} is {total_order}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn sobol_indices_of_a_constant_output_are_nan() {
+        let blocks = [[1.0f32; 3]; 4];
+        let blocks = blocks.iter().map(|b| &b[..]).collect::<Vec<_>>();
+        let (first_order, total_order) = sobol_indices(&blocks, &[0, 1, 2, 3], 0);
+        assert!(first_order.is_nan() && total_order.is_nan());
+    }
+
+    #[test]
+    fn lane_speed_factor_covers_its_bounds() {
+        let mut point = ParameterPoint {
+            density: 0.1,
+            deceleration_probability: 0.2,
+            lane_change_probability: 0.4,
+            lane_speeds: vec![5, 5, 5],
+            road_length: 100,
+        };
+        let factor = SensitivityFactor::LaneSpeed(1, 200, 254);
+
+        factor.apply(&mut point, 0.0);
+        assert_eq!(point.lane_speeds, [5, 200, 5]);
+        factor.apply(&mut point, 0.999_999);
+        assert_eq!(point.lane_speeds, [5, 254, 5]);
+    }
+}
@@ -1,11 +1,24 @@
 use crate::{
+    calibration::read_observations,
     iterations_runner::run_iterations,
+    rng::derive_seed,
     road::create_road,
-    typedef::{IterationInfo, MetaData, SimulationType, SimulationWriter, SimulationsHandler},
+    typedef::{
+        AnimationFormat, AnimationRecorder, Checkpoint, ExperimentConfig, ExperimentError,
+        GapHistograms, InitialCondition, IterationInfo, JamStatistics, MetaData, ParameterPoint,
+        Road, RoadStateError, SampleStatistics, SimulationType, SimulationWriter,
+        SimulationsHandler, StateFormat, SweepBranch, SweepConfig, TrajectoryFormat,
+        TrajectoryRecorder, WriterError,
+    },
 };
 use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::error;
 
 impl SimulationsHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         num_simulations: usize,
         iterations_per_simulation: usize,
@@ -16,6 +29,21 @@ impl SimulationsHandler {
         verbose: bool,
         lane_speeds: Vec<u8>,
         pretty_print: bool,
+        road_length: usize,
+        standard_density: f32,
+        seed: u64,
+        track_jams: bool,
+        record_vehicle_statistics: bool,
+        trajectory_recorder: Option<TrajectoryRecorder>,
+        animation_recorder: Option<AnimationRecorder>,
+        record_gap_histograms: bool,
+        record_lane_change_steps: bool,
+        warmup_steps: usize,
+        checkpoint: Option<Checkpoint>,
+        initial_condition: InitialCondition,
+        initial_state: Option<Road>,
+        dump_state_at: Option<usize>,
+        state_format: StateFormat,
     ) -> Self {
         Self {
             num_simulations,
@@ -27,171 +55,649 @@ impl SimulationsHandler {
             verbose,
             lane_speeds,
             pretty_print,
+            road_length,
+            standard_density,
+            seed,
+            track_jams,
+            record_vehicle_statistics,
+            trajectory_recorder,
+            animation_recorder,
+            record_gap_histograms,
+            record_lane_change_steps,
+            warmup_steps,
+            checkpoint,
+            initial_condition,
+            initial_state,
+            dump_state_at,
+            state_format,
+            show_progress: true,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            observations: Vec::new(),
+            calibration_history: Mutex::new(Vec::new()),
+            output_error: Mutex::new(None),
         }
     }
 
+    /// Create a handler that runs a resolved experiment
+    /// # Arguments
+    /// * `experiment` - The experiment to run, with its seed and output name resolved
+    /// * `verbose` - Print progress information
+    /// * `pretty_print` - Show every step of the simulation in the terminal
+    /// * `resume` - Continue from the checkpoint of a previous run of the same experiment
+    pub fn from_experiment(
+        experiment: &ExperimentConfig,
+        verbose: bool,
+        pretty_print: bool,
+        resume: bool,
+    ) -> Result<Self, ExperimentError> {
+        experiment.validate()?;
+
+        let seed = experiment.seed.ok_or_else(|| {
+            ExperimentError::Invalid("the experiment has not been resolved".to_string())
+        })?;
+        let simulation_writer =
+            SimulationWriter::new(experiment.output_file_path(), experiment.output.format);
+        if let Some(directory) = simulation_writer.file_path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        let checkpoint_path = simulation_writer.sibling_file_path("checkpoint");
+        let checkpoint = if resume {
+            Checkpoint::resume(&checkpoint_path, experiment)?
+        } else {
+            Checkpoint::create(&checkpoint_path, experiment)?
+        };
+        if verbose && resume {
+            println!(
+                "Resuming with {} completed parameter points",
+                checkpoint.completed.len()
+            );
+        }
+
+        let output = &experiment.output;
+        let trajectory_recorder = output
+            .trajectories
+            .map(|format| {
+                let extension = match format {
+                    TrajectoryFormat::Csv => "trajectories.csv",
+                    TrajectoryFormat::Binary => "trajectories.bin",
+                };
+                let vehicle_ids = (!output.trajectory_vehicles.is_empty())
+                    .then(|| output.trajectory_vehicles.iter().copied().collect());
+
+                TrajectoryRecorder::new(
+                    &simulation_writer.sibling_file_path(extension),
+                    format,
+                    output.trajectory_start..output.trajectory_end.unwrap_or(usize::MAX),
+                    vehicle_ids,
+                    resume.then(|| checkpoint.completed.keys().copied().collect()),
+                )
+            })
+            .transpose()?;
+
+        let animation_recorder = output.animation.map(|format| {
+            let extension = match format {
+                AnimationFormat::Cast => "cast",
+                AnimationFormat::Gif => "gif",
+            };
+
+            AnimationRecorder::new(
+                &simulation_writer.sibling_file_path(extension),
+                format,
+                output.animation_simulation,
+                output.animation_iteration.unwrap_or(1),
+                Duration::from_millis(output.animation_frame_ms.unwrap_or(150)),
+            )
+        });
+
+        let initial_state = experiment
+            .road
+            .initial_state
+            .as_deref()
+            .map(Road::load_state)
+            .transpose()?;
+
+        let mut handler = Self::new(
+            experiment.repetitions,
+            experiment.iterations,
+            experiment.probabilities.deceleration,
+            experiment.probabilities.lane_change,
+            experiment.simulation_type(),
+            simulation_writer,
+            verbose,
+            experiment.road.lane_speeds.clone(),
+            pretty_print,
+            experiment.road.length as usize,
+            experiment.road.standard_density,
+            seed,
+            output.track_jams,
+            output.vehicle_statistics,
+            trajectory_recorder,
+            animation_recorder,
+            output.gap_histograms,
+            output.lane_change_steps,
+            output.warmup,
+            Some(checkpoint),
+            experiment.road.initial_condition.clone(),
+            initial_state,
+            output.dump_state_at,
+            output.state_format,
+        );
+        if let SweepConfig::Calibration { observations, .. } = &experiment.sweep {
+            handler.observations = read_observations(observations)?;
+        }
+
+        Ok(handler)
+    }
+
+    /// A progress bar over a number of parameter points, hidden when progress is not shown
+    pub(crate) fn progress_bar(&self, len: u64) -> ProgressBar {
+        if !self.show_progress {
+            return ProgressBar::hidden();
+        }
+
+        let bar = ProgressBar::new(len);
+        //set width of progress bar
+        bar.set_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+            )
+            .unwrap()
+            .progress_chars("##-"),
+        );
+        bar
+    }
+
+    /// Stop the run after the parameter point that is being simulated
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the run was stopped before every parameter point was simulated,
+    /// by the interrupt flag or by an output that could not be written
+    pub fn was_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst) || !self.is_recording()
+    }
+
+    /// The flag that stops the run when set, for use from a signal handler
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.interrupted.clone()
+    }
+
+    /// Delete the checkpoint once the results of a complete run have been saved
+    pub fn remove_checkpoint(&self) -> Result<(), ExperimentError> {
+        match &self.checkpoint {
+            Some(checkpoint) => checkpoint.remove(),
+            None => Ok(()),
+        }
+    }
+
+    /// The result of a point completed by a previous run
+    pub(crate) fn completed_point(
+        &self,
+        simulation: usize,
+        iteration: usize,
+    ) -> Option<IterationInfo> {
+        self.checkpoint
+            .as_ref()
+            .and_then(|c| c.completed_point(simulation, iteration))
+            .cloned()
+    }
+
+    /// Whether the trajectories, the animation and the road states are still recorded
+    pub(crate) fn is_recording(&self) -> bool {
+        self.output_error.lock().unwrap().is_none()
+    }
+
+    /// Stop recording after an output could not be written, which stops the run after the current parameter point.
+    /// Only the first error is kept, it is returned by `take_output_error`.
+    /// The interrupt flag is left alone, as it may be shared with the other experiments of a batch.
+    pub(crate) fn output_failed(&self, e: ExperimentError) {
+        error!("{e}, stopping after the current parameter point");
+        self.output_error.lock().unwrap().get_or_insert(e);
+    }
+
+    /// The error that stopped the recording of the outputs, if any
+    pub fn take_output_error(&self) -> Option<ExperimentError> {
+        self.output_error.lock().unwrap().take()
+    }
+
+    /// Add a completed point to the checkpoint.
+    /// A point that cannot be recorded is simulated again when the run is resumed.
+    /// Once the outputs have failed no point is added, so a resumed run records the missing outputs again.
+    pub(crate) fn record_point(&self, simulation: usize, iteration: usize, info: &IterationInfo) {
+        if !self.is_recording() {
+            return;
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Err(e) = checkpoint.record(simulation, iteration, info) {
+                error!("Failed to record point {iteration} of simulation {simulation} in the checkpoint: {e}");
+            }
+        }
+    }
+
+    /// The seed of one set of parameters in one simulation, derived from the seed of the handler
+    pub fn point_seed(&self, simulation: usize, iteration: usize) -> u64 {
+        derive_seed(self.seed, &[simulation as u64, iteration as u64])
+    }
+
     pub fn run_simulation(
         &self,
+        simulation: usize,
         iterations_per_simulation: usize,
         sim_type: SimulationType,
     ) -> Vec<IterationInfo> {
-        let road_length = 100;
-        let standard_density = 0.3;
-        // let deceleration_probability = 0.4;
-        // let lane_change_probability = 0.8;
+        let road_length = self.road_length;
 
         let mut iteration = 0;
         let mut iteration_infos = Vec::new();
 
         match sim_type {
-            SimulationType::Density(start, end, step) => {
-                let float_range = float_range_step(start, end, step);
-
-                let bar = ProgressBar::new(float_range.len() as u64);
-
-                //set width of progress bar
-                bar.set_style(
-                    ProgressStyle::with_template(
-                        "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-                    )
-                    .unwrap()
-                    .progress_chars("##-"),
+            SimulationType::Hysteresis(start, end, step) => {
+                let up = float_range_step(start, end, step);
+                let down = up.iter().rev().skip(1).copied().collect::<Vec<_>>();
+                let points = up
+                    .iter()
+                    .map(|density| (SweepBranch::Up, *density))
+                    .chain(down.iter().map(|density| (SweepBranch::Down, *density)))
+                    .collect::<Vec<_>>();
+
+                // The points depend on each other, so only a simulation that was completed as a whole is reused
+                let completed = (1..=points.len())
+                    .map(|iteration| self.completed_point(simulation, iteration))
+                    .collect::<Option<Vec<_>>>();
+                if let Some(completed) = completed {
+                    return completed;
+                }
+
+                let bar = self.progress_bar(points.len() as u64);
+
+                // Every point continues from the final state of the previous point
+                let mut road = create_road(
+                    road_length,
+                    start,
+                    self.lane_speeds.clone(),
+                    self.deceleration_probability,
+                    self.lane_change_probability,
+                    &self.initial_condition,
+                    self.point_seed(simulation, 0),
                 );
 
-                for i in float_range {
+                for (branch, density) in points {
+                    if self.was_interrupted() {
+                        break;
+                    }
                     bar.inc(1);
                     iteration += 1;
-                    let road = create_road(
-                        road_length,
-                        i,
-                        self.lane_speeds.clone(),
-                        self.deceleration_probability,
-                        self.lane_change_probability,
-                        true,
-                        true,
+                    road.set_density(density, self.lane_change_probability);
+                    road.reset_statistics();
+
+                    let point = ParameterPoint {
+                        density,
+                        ..self.standard_point()
+                    };
+                    let (mut iteration_info, final_road) = run_iterations(
+                        self,
+                        simulation,
+                        iteration,
+                        iterations_per_simulation,
+                        road,
+                        &point,
                     );
-                    let iteration_info = run_iterations(iteration, iterations_per_simulation, road, self.pretty_print);
+                    iteration_info.branch = Some(branch);
+                    self.record_point(simulation, iteration, &iteration_info);
                     iteration_infos.push(iteration_info);
+                    road = final_road;
                 }
 
                 bar.finish();
             }
-            SimulationType::LaneChange(start, end, step) => {
-                let float_range = float_range_step(start, end, step);
-                let bar = ProgressBar::new(float_range.len() as u64);
+            sim_type => {
+                let points = self.parameter_points(&sim_type);
+
+                let bar = self.progress_bar(points.len() as u64);
 
-                for i in float_range {
+                for point in points {
+                    if self.was_interrupted() {
+                        break;
+                    }
                     bar.inc(1);
                     iteration += 1;
-                    let road = create_road(
-                        road_length,
-                        standard_density,
-                        self.lane_speeds.clone(),
-                        self.deceleration_probability,
-                        i,
-                        true,
-                        true,
+
+                    if let Some(iteration_info) = self.completed_point(simulation, iteration) {
+                        iteration_infos.push(iteration_info);
+                        continue;
+                    }
+
+                    let road =
+                        self.initial_road(point.clone(), self.point_seed(simulation, iteration));
+                    let (iteration_info, _) = run_iterations(
+                        self,
+                        simulation,
+                        iteration,
+                        iterations_per_simulation,
+                        road,
+                        &point,
                     );
-                    let iteration_info = run_iterations(iteration, iterations_per_simulation, road, self.pretty_print);
+                    self.record_point(simulation, iteration, &iteration_info);
                     iteration_infos.push(iteration_info);
                 }
 
                 bar.finish();
             }
-            SimulationType::Deceleration(start, end, step) => {
-                let float_range = float_range_step(start, end, step);
+        };
 
-                let bar = ProgressBar::new(float_range.len() as u64);
+        iteration_infos
+    }
 
-                for i in float_range {
-                    bar.inc(1);
-                    iteration += 1;
-                    let road = create_road(
-                        road_length,
-                        standard_density,
-                        self.lane_speeds.clone(),
-                        i,
-                        self.deceleration_probability,
-                        true,
-                        true,
+    /// Run every simulation of one set of parameters and average them
+    /// # Arguments
+    /// * `iteration` - The number of the point, under which it is checkpointed
+    /// * `seed_iteration` - The number of the point whose seeds are used, points that share it see the same random numbers
+    /// * `point` - The parameters of the point
+    /// # Returns
+    /// The averaged results, or `None` if the run was interrupted before the first simulation finished
+    pub(crate) fn run_averaged_point(
+        &self,
+        iteration: usize,
+        seed_iteration: usize,
+        point: ParameterPoint,
+    ) -> Option<IterationInfo> {
+        let mut sims = Vec::new();
+
+        for simulation in 0..self.num_simulations {
+            if self.was_interrupted() {
+                break;
+            }
+
+            let iteration_info = match self.completed_point(simulation, iteration) {
+                Some(iteration_info) => iteration_info,
+                None => {
+                    let road = self
+                        .initial_road(point.clone(), self.point_seed(simulation, seed_iteration));
+                    let (iteration_info, _) = run_iterations(
+                        self,
+                        simulation,
+                        iteration,
+                        self.iterations_per_simulation,
+                        road,
+                        &point,
                     );
-                    let iteration_info = run_iterations(iteration, iterations_per_simulation, road, self.pretty_print);
-                    iteration_infos.push(iteration_info);
+                    self.record_point(simulation, iteration, &iteration_info);
+                    iteration_info
                 }
+            };
+            sims.push(vec![iteration_info]);
+        }
 
-                bar.finish();
-            }
+        self.average_of_simulations(sims).pop()
+    }
+
+    /// The parameters of the experiment, as used by every point of a sweep except for the swept parameters
+    pub(crate) fn standard_point(&self) -> ParameterPoint {
+        ParameterPoint {
+            density: self.standard_density,
+            deceleration_probability: self.deceleration_probability,
+            lane_change_probability: self.lane_change_probability,
+            lane_speeds: self.lane_speeds.clone(),
+            road_length: self.road_length,
+        }
+    }
+
+    /// The road a set of parameters starts from, a random road unless an initial state is given.
+    /// A road from an initial state keeps its vehicles and lane speeds, but takes the probabilities of the point.
+    /// # Arguments
+    /// * `point` - The parameters of the road
+    /// * `seed` - The seed of the point
+    pub(crate) fn initial_road(&self, point: ParameterPoint, seed: u64) -> Road {
+        let Some(initial_state) = &self.initial_state else {
+            return create_road(
+                point.road_length,
+                point.density,
+                point.lane_speeds,
+                point.deceleration_probability,
+                point.lane_change_probability,
+                &self.initial_condition,
+                seed,
+            );
         };
 
-        iteration_infos
+        let mut road = initial_state.clone();
+        road.deceleration_probability = point.deceleration_probability;
+        for vehicle in road.vehicles.iter_mut() {
+            vehicle.move_left_chance = point.lane_change_probability;
+            vehicle.move_right_chance = point.lane_change_probability;
+        }
+        // Without a new seed every simulation of the point would be identical
+        road.seed = seed;
+        road.reset_statistics();
+        road
+    }
+
+    /// Save the state of the road to `<name>.state_<simulation>_<iteration>_<step>.<extension>`
+    pub fn dump_state(
+        &self,
+        simulation: usize,
+        iteration: usize,
+        step: usize,
+        road: &Road,
+    ) -> Result<(), RoadStateError> {
+        let file_path = self.simulation_writer.sibling_file_path(&format!(
+            "state_{simulation}_{iteration}_{step}.{}",
+            self.state_format.extension()
+        ));
+        road.save_state(&file_path, self.state_format)
+    }
+
+    /// The sets of parameters of a sweep, in the order in which they are simulated.
+    /// Grid sweeps vary the density fastest and the road length slowest.
+    /// Hysteresis, adaptive and calibration sweeps have no points known in advance and return an empty list.
+    pub fn parameter_points(&self, sim_type: &SimulationType) -> Vec<ParameterPoint> {
+        let standard_point = self.standard_point();
+
+        match *sim_type {
+            SimulationType::Density(start, end, step) => float_range_step(start, end, step)
+                .into_iter()
+                .map(|density| ParameterPoint {
+                    density,
+                    ..standard_point.clone()
+                })
+                .collect(),
+            SimulationType::LaneChange(start, end, step) => float_range_step(start, end, step)
+                .into_iter()
+                .map(|lane_change_probability| ParameterPoint {
+                    lane_change_probability,
+                    ..standard_point.clone()
+                })
+                .collect(),
+            SimulationType::Deceleration(start, end, step) => float_range_step(start, end, step)
+                .into_iter()
+                .map(|deceleration_probability| ParameterPoint {
+                    deceleration_probability,
+                    ..standard_point.clone()
+                })
+                .collect(),
+            SimulationType::LaneSpeed(lane, start, end) => (start..=end)
+                .map(|speed| {
+                    let mut lane_speeds = self.lane_speeds.clone();
+                    lane_speeds[lane] = speed;
+                    ParameterPoint {
+                        lane_speeds,
+                        ..standard_point.clone()
+                    }
+                })
+                .collect(),
+            SimulationType::SpeedDifferential(start, end) => (start..=end)
+                .map(|differential| {
+                    let lanes = self.lane_speeds.len();
+                    let fastest = self.lane_speeds[lanes - 1];
+                    let lane_speeds = (0..lanes)
+                        .map(|lane| fastest - differential * (lanes - 1 - lane) as u8)
+                        .collect();
+                    ParameterPoint {
+                        lane_speeds,
+                        ..standard_point.clone()
+                    }
+                })
+                .collect(),
+            SimulationType::Hysteresis(..)
+            | SimulationType::AdaptiveDensity(..)
+            | SimulationType::Calibration(..) => Vec::new(),
+            SimulationType::Grid(ref grid) => {
+                let mut points = Vec::new();
+                for road_length in &grid.road_lengths {
+                    for lane_speeds in &grid.lane_speeds {
+                        for lane_change_probability in &grid.lane_change_probabilities {
+                            for deceleration_probability in &grid.deceleration_probabilities {
+                                for density in &grid.densities {
+                                    points.push(ParameterPoint {
+                                        density: *density,
+                                        deceleration_probability: *deceleration_probability,
+                                        lane_change_probability: *lane_change_probability,
+                                        lane_speeds: lane_speeds.clone(),
+                                        road_length: *road_length,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+                points
+            }
+            SimulationType::Sensitivity(ref design) => design.points(&standard_point, self.seed),
+        }
     }
 
     pub fn run_simulations(&self) -> Vec<IterationInfo> {
+        if let SimulationType::AdaptiveDensity(start, end, budget) = self.sim_type {
+            return self.run_adaptive_density(start, end, budget);
+        }
+        if let SimulationType::Calibration(search) = &self.sim_type {
+            return self.run_calibration(search);
+        }
+
         let mut sim_infos = Vec::new();
 
-        for _ in 0..self.num_simulations {
+        for simulation in 0..self.num_simulations {
             if self.verbose {
-                print!(
-                    "Running simulation {} of {}\n",
+                println!(
+                    "Running simulation {} of {}",
                     sim_infos.len() + 1,
                     self.num_simulations
                 );
             }
-            let simulation_results =
-                self.run_simulation(self.iterations_per_simulation, self.sim_type.clone());
+            let simulation_results = self.run_simulation(
+                simulation,
+                self.iterations_per_simulation,
+                self.sim_type.clone(),
+            );
             sim_infos.push(simulation_results);
-        }
 
-        self.average_of_simulations(sim_infos)
-    }
+            if self.was_interrupted() {
+                break;
+            }
+        }
 
-    fn average_of_simulations(&self, sims: Vec<Vec<IterationInfo>>) -> Vec<IterationInfo> {
         if self.verbose {
-            print!("Calculating averages of simulations\n");
+            println!("Calculating averages of simulations");
         }
+        self.average_of_simulations(sim_infos)
+    }
 
+    pub(crate) fn average_of_simulations(
+        &self,
+        sims: Vec<Vec<IterationInfo>>,
+    ) -> Vec<IterationInfo> {
         let mut average_infos: Vec<IterationInfo> = Vec::new();
 
-        let num_of_rows = sims[0].len();
+        // An interrupted run can end with a simulation that did not complete every point
+        let num_of_rows = sims.iter().map(|sim| sim.len()).max().unwrap_or(0);
 
         for i in 0..num_of_rows {
-            let mut sum_time: f32 = 0.0;
-            let mut sum_speed: f32 = 0.0;
-            let mut sum_speed_per_lane: Vec<f32> = vec![0.0, 0.0, 0.0];
-            let mut sum_flow: f32 = 0.0;
-
-            let iter_info = &sims[0][i];
-
-            for j in 0..self.num_simulations {
-                let current_sim = &sims[j];
-                let current_sim_time = current_sim[i].time.as_secs_f32();
-                let current_sim_speed = current_sim[i].average_speed;
-                let current_sim_speed_per_lane = current_sim[i].average_speed_per_lane.clone();
-                let current_sim_flow = current_sim[i].flow;
-
-                sum_time += current_sim_time;
-                sum_speed += current_sim_speed;
-                sum_speed_per_lane[0] += current_sim_speed_per_lane[0];
-                sum_speed_per_lane[1] += current_sim_speed_per_lane[1];
-                sum_speed_per_lane[2] += current_sim_speed_per_lane[2];
-                sum_flow += current_sim_flow;
-            }
+            let rows = sims
+                .iter()
+                .take(self.num_simulations)
+                .filter_map(|sim| sim.get(i))
+                .collect::<Vec<_>>();
+            let iter_info = rows[0];
+
+            let sum_time = rows.iter().map(|row| row.time.as_secs_f32()).sum::<f32>();
+            let statistics_of = |metric: &dyn Fn(&IterationInfo) -> f32| {
+                SampleStatistics::from_samples(rows.iter().map(|row| metric(row)))
+            };
 
-            // let average_time = sum_time / self.num_simulations as f32;
-            let average_speed = sum_speed / self.num_simulations as f32;
-            let average_speed_per_lane = vec![
-                sum_speed_per_lane[0] / self.num_simulations as f32,
-                sum_speed_per_lane[1] / self.num_simulations as f32,
-                sum_speed_per_lane[2] / self.num_simulations as f32,
-            ];
-            let average_flow = sum_flow / self.num_simulations as f32;
+            let speed = statistics_of(&|row| row.average_speed);
+            let speed_per_lane = (0..iter_info.average_speed_per_lane.len())
+                .map(|lane| statistics_of(&|row| row.average_speed_per_lane[lane]))
+                .collect::<Vec<_>>();
+            let flow = statistics_of(&|row| row.flow);
+            let lane_changes_left = statistics_of(&|row| row.lane_changes_left);
+            let lane_changes_right = statistics_of(&|row| row.lane_changes_right);
+            let lane_changes_from_lane = (0..iter_info.lane_changes_from_lane.len())
+                .map(|lane| statistics_of(&|row| row.lane_changes_from_lane[lane]))
+                .collect::<Vec<_>>();
+            let unsafe_lane_changes = statistics_of(&|row| row.unsafe_lane_changes);
+            let lane_changes_per_step = statistics_of(&|row| row.lane_changes_per_step);
+            let lane_change_rate = statistics_of(&|row| row.lane_change_rate);
 
-            let average_info = iter_info.clone().add_averages_to_info(
+            let mut jam_statistics: Option<JamStatistics> = None;
+            let mut gap_histograms: Option<GapHistograms> = None;
+            let mut lane_changes_by_step: Option<Vec<f32>> = None;
+            for row in &rows {
+                if let Some(row_jams) = &row.jam_statistics {
+                    jam_statistics
+                        .get_or_insert_with(JamStatistics::default)
+                        .merge(row_jams);
+                }
+                if let Some(row_gaps) = &row.gap_histograms {
+                    gap_histograms
+                        .get_or_insert_with(|| GapHistograms::new(row_gaps.gaps_per_lane.len()))
+                        .merge(row_gaps);
+                }
+                if let Some(row_steps) = &row.lane_changes_by_step {
+                    let sums =
+                        lane_changes_by_step.get_or_insert_with(|| vec![0.0; row_steps.len()]);
+                    for (sum, count) in sums.iter_mut().zip(row_steps) {
+                        *sum += count / rows.len() as f32;
+                    }
+                }
+            }
+
+            let mut average_info = iter_info.clone().add_averages_to_info(
                 sum_time,
-                average_speed,
-                average_speed_per_lane,
-                average_flow,
+                speed.mean,
+                speed_per_lane.iter().map(|s| s.mean).collect(),
+                flow.mean,
             );
+            average_info.lane_changes_left = lane_changes_left.mean;
+            average_info.lane_changes_right = lane_changes_right.mean;
+            average_info.lane_changes_from_lane =
+                lane_changes_from_lane.iter().map(|s| s.mean).collect();
+            average_info.unsafe_lane_changes = unsafe_lane_changes.mean;
+            average_info.lane_changes_per_step = lane_changes_per_step.mean;
+            average_info.lane_change_rate = lane_change_rate.mean;
+            average_info.jam_statistics = jam_statistics;
+            average_info.gap_histograms = gap_histograms;
+            average_info.lane_changes_by_step = lane_changes_by_step;
+            average_info.final_vehicles = rows
+                .iter()
+                .flat_map(|row| row.final_vehicles.clone())
+                .collect();
+
+            let mut metric_statistics = vec![("average_speed".to_string(), speed)];
+            for (lane, s) in speed_per_lane.into_iter().enumerate() {
+                metric_statistics.push((format!("average_speed_lane_{lane}"), s));
+            }
+            metric_statistics.push(("flow".to_string(), flow));
+            metric_statistics.push(("lane_changes_left".to_string(), lane_changes_left));
+            metric_statistics.push(("lane_changes_right".to_string(), lane_changes_right));
+            for (lane, s) in lane_changes_from_lane.into_iter().enumerate() {
+                metric_statistics.push((format!("lane_changes_from_lane_{lane}"), s));
+            }
+            metric_statistics.push(("unsafe_lane_changes".to_string(), unsafe_lane_changes));
+            metric_statistics.push(("lane_changes_per_step".to_string(), lane_changes_per_step));
+            metric_statistics.push(("lane_change_rate".to_string(), lane_change_rate));
+            average_info.metric_statistics = metric_statistics;
 
             average_infos.push(average_info);
         }
@@ -201,18 +707,37 @@ impl SimulationsHandler {
 
     pub fn save_simulation_results(
         &self,
-        iteration_infos: &Vec<IterationInfo>,
+        iteration_infos: &[IterationInfo],
         metadata: &MetaData,
-    ) {
+    ) -> Result<(), WriterError> {
         if self.verbose {
-            print!("Writing simulation results to csv\n");
+            println!("Writing simulation results");
         }
         self.simulation_writer
-            .save_csv_and_metadata(iteration_infos, metadata);
+            .save_results_and_metadata(iteration_infos, metadata)?;
+
+        match &self.sim_type {
+            SimulationType::Sensitivity(design) => {
+                let indices = design.indices(iteration_infos, self.seed);
+                self.simulation_writer
+                    .write_sensitivity_indices_to_csv(&indices)?;
+            }
+            SimulationType::Calibration(_) => {
+                if let Some(result) = self.calibration_result() {
+                    self.simulation_writer.write_calibration_history_to_csv(
+                        &self.calibration_history.lock().unwrap(),
+                    )?;
+                    self.simulation_writer.write_calibration_result(&result)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
     }
 }
 
-fn float_range_step(start: f32, end: f32, step: f32) -> Vec<f32> {
+pub(crate) fn float_range_step(start: f32, end: f32, step: f32) -> Vec<f32> {
     let mut range = Vec::new();
     let mut i = start;
     while i < end {
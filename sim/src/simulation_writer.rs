@@ -1,100 +1,477 @@
-use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use crate::typedef::{IterationInfo, MetaData, SimulationWriter};
+use crate::typedef::{
+    CalibrationCandidate, CalibrationResult, CsvResultWriter, IterationInfo, JsonLinesResultWriter,
+    MetaData, ResultFormat, ResultWriter, SensitivityIndex, SimulationWriter, WriterError,
+};
 
-const CSV_DELIMITER: &str = ",";
+/// The extensions of the files of averaged results
+pub const RESULT_EXTENSIONS: [&str; 2] = ["csv", "jsonl"];
+
+/// Path of a file next to the results, with the given extension instead of the extension of the results.
+/// Only a result extension is replaced, so the dots of a name like `a_0.4` are kept.
+/// # Arguments
+/// * `results` - The file of the averaged results
+/// * `extension` - The extension of the file next to the results, without the leading dot
+pub fn sibling_file_path(results: &Path, extension: &str) -> PathBuf {
+    let has_result_extension = results
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| RESULT_EXTENSIONS.contains(&e));
+    let base = if has_result_extension {
+        results.with_extension("")
+    } else {
+        results.to_path_buf()
+    };
+
+    let mut file_name = base.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    base.with_file_name(file_name)
+}
 
 impl SimulationWriter {
-    pub fn new(file_path: &str) -> Self {
-        Self {
-            file_path: PathBuf::from(".").join(PathBuf::from("data").join(file_path)),
-        }
+    /// # Arguments
+    /// * `file_path` - The file of the averaged results, every other output is written next to it
+    /// * `format` - The format of the averaged results
+    pub fn new(file_path: PathBuf, format: ResultFormat) -> Self {
+        Self { file_path, format }
     }
 
-    pub fn initialize_csv(&self) {
-        let header = format!(
-            "iteration{d}time{d}density{d}average_speed{d}average_speed_lane_0{d}average_speed_lane_1{d}average_speed_lane_2{d}lane_change_probability{d}deceleration_probability{d}max_speed_lane_1{d}max_speed_lane_2{d}max_speed_lane_3{d}flow{d}vehicle_count\n",
-            d = CSV_DELIMITER
-        );
+    /// The writer of the averaged results, replacing any earlier results
+    fn result_writer(&self) -> Result<Box<dyn ResultWriter>, WriterError> {
+        Ok(match self.format {
+            ResultFormat::Csv => Box::new(CsvResultWriter::new(BufWriter::new(File::create(
+                &self.file_path,
+            )?))),
+            ResultFormat::JsonLines => Box::new(JsonLinesResultWriter::new(BufWriter::new(
+                File::create(&self.file_path)?,
+            ))),
+            ResultFormat::Stdout => Box::new(CsvResultWriter::new(std::io::stdout())),
+        })
+    }
 
-        let mut f = fs::File::create(&self.file_path).unwrap();
-        f.write_all(header.as_bytes()).unwrap();
-    }
-
-    pub fn save_iteration_to_csv(&self, i_inf: &IterationInfo) {
-        let csv = format!(
-            "{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}\n",
-            i_inf.iteration,
-            i_inf.time.as_secs_f32(),
-            i_inf.density,
-            i_inf
-                .average_speed
-                .is_nan()
-                .then(|| 0.0)
-                .unwrap_or_else(|| i_inf.average_speed),
-            i_inf.average_speed_per_lane[0]
-                .is_nan()
-                .then(|| 0.0)
-                .unwrap_or_else(|| i_inf.average_speed_per_lane[0]),
-            i_inf.average_speed_per_lane[1]
-                .is_nan()
-                .then(|| 0.0)
-                .unwrap_or_else(|| i_inf.average_speed_per_lane[1]),
-            i_inf.average_speed_per_lane[2]
-                .is_nan()
-                .then(|| 0.0)
-                .unwrap_or_else(|| i_inf.average_speed_per_lane[2]),
-            i_inf.lane_change_probability,
-            i_inf.deceleration_probability,
-            i_inf.max_speed_per_lane[0],
-            i_inf.max_speed_per_lane[1],
-            i_inf.max_speed_per_lane[2],
-            i_inf.flow,
-            i_inf.vehicle_count,
-            d = CSV_DELIMITER,
-        );
+    pub fn save_results_and_metadata(
+        &self,
+        iteration_infos: &[IterationInfo],
+        metadata: &MetaData,
+    ) -> Result<(), WriterError> {
+        self.write_iteration_infos(iteration_infos)?;
+        self.write_metadata_to_file(metadata)?;
+
+        if iteration_infos.iter().any(|i| i.jam_statistics.is_some()) {
+            self.write_jam_statistics_to_csv(iteration_infos)?;
+        }
+
+        if iteration_infos.iter().any(|i| i.gap_histograms.is_some()) {
+            self.write_gap_histograms_to_csv(iteration_infos)?;
+        }
+
+        if iteration_infos
+            .iter()
+            .any(|i| i.lane_changes_by_step.is_some())
+        {
+            self.write_lane_changes_by_step_to_csv(iteration_infos)?;
+        }
+
+        if iteration_infos.iter().any(|i| !i.final_vehicles.is_empty()) {
+            self.write_vehicle_statistics_to_csv(iteration_infos)?;
+        }
 
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .append(true)
-            .create(true)
-            .open(&self.file_path)
-            .unwrap();
+        Ok(())
+    }
 
-        file.write_all(csv.as_bytes()).unwrap();
+    /// Path of a file next to the results, with the given extension instead of the extension of the results
+    pub fn sibling_file_path(&self, extension: &str) -> PathBuf {
+        sibling_file_path(&self.file_path, extension)
     }
 
-    pub fn save_csv_and_metadata(&self, iteration_infos: &Vec<IterationInfo>, metadata: &MetaData) {
-        self.write_iteration_infos_to_csv(iteration_infos);
-        self.write_metadata_to_file(metadata);
+    /// Create a csv file next to the results and write its header
+    fn create_csv(
+        &self,
+        extension: &str,
+        header: &[&str],
+    ) -> Result<csv::Writer<File>, WriterError> {
+        let mut writer = csv::Writer::from_path(self.sibling_file_path(extension))?;
+        writer.write_record(header)?;
+        Ok(writer)
     }
 
-    pub fn write_iteration_infos_to_csv(&self, iteration_infos: &Vec<IterationInfo>) {
-        self.initialize_csv();
+    /// Write the averaged results of every parameter point in the format of the writer
+    pub fn write_iteration_infos(
+        &self,
+        iteration_infos: &[IterationInfo],
+    ) -> Result<(), WriterError> {
+        let metric_names = iteration_infos
+            .first()
+            .map(|i| {
+                i.metric_statistics
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut writer = self.result_writer()?;
+        writer.write_header(&metric_names)?;
         for iteration_info in iteration_infos {
-            self.save_iteration_to_csv(iteration_info);
+            writer.write_row(iteration_info)?;
+        }
+        writer.finish()
+    }
+
+    /// Write the jam summary of every iteration to `<name>.jams.csv`
+    /// and the jam size and lifetime distributions to `<name>.jam_distribution.csv`
+    pub fn write_jam_statistics_to_csv(
+        &self,
+        iteration_infos: &[IterationInfo],
+    ) -> Result<(), WriterError> {
+        let mut summary = self.create_csv(
+            "jams.csv",
+            &[
+                "iteration",
+                "density",
+                "lane_change_probability",
+                "deceleration_probability",
+                "jam_count",
+                "dissolved_jam_count",
+                "mean_jam_size",
+                "max_jam_size",
+                "mean_jam_lifetime",
+                "mean_wave_velocity",
+            ],
+        )?;
+        let mut distribution = self.create_csv(
+            "jam_distribution.csv",
+            &["iteration", "density", "quantity", "value", "count"],
+        )?;
+
+        for i_inf in iteration_infos {
+            let Some(jams) = &i_inf.jam_statistics else {
+                continue;
+            };
+
+            summary.serialize((
+                i_inf.iteration,
+                i_inf.density,
+                i_inf.lane_change_probability,
+                i_inf.deceleration_probability,
+                jams.jam_count,
+                jams.dissolved_count,
+                jams.mean_size(),
+                jams.max_size(),
+                jams.mean_lifetime(),
+                jams.mean_wave_velocity(),
+            ))?;
+
+            let quantities = [
+                ("size", &jams.size_distribution),
+                ("lifetime", &jams.lifetime_distribution),
+            ];
+            for (quantity, values) in quantities {
+                for (value, count) in values {
+                    distribution.serialize((
+                        i_inf.iteration,
+                        i_inf.density,
+                        quantity,
+                        value,
+                        count,
+                    ))?;
+                }
+            }
+        }
+
+        summary.flush()?;
+        distribution.flush()?;
+        Ok(())
+    }
+
+    /// Write the mean number of lane changes in every step of every iteration to `<name>.lane_changes.csv`.
+    /// Step 1 is the first step after the initial state of the road.
+    pub fn write_lane_changes_by_step_to_csv(
+        &self,
+        iteration_infos: &[IterationInfo],
+    ) -> Result<(), WriterError> {
+        let mut csv = self.create_csv(
+            "lane_changes.csv",
+            &[
+                "iteration",
+                "density",
+                "lane_change_probability",
+                "deceleration_probability",
+                "step",
+                "lane_changes",
+            ],
+        )?;
+
+        for i_inf in iteration_infos {
+            let Some(lane_changes_by_step) = &i_inf.lane_changes_by_step else {
+                continue;
+            };
+            for (step, lane_changes) in lane_changes_by_step.iter().enumerate() {
+                csv.serialize((
+                    i_inf.iteration,
+                    i_inf.density,
+                    i_inf.lane_change_probability,
+                    i_inf.deceleration_probability,
+                    step + 1,
+                    lane_changes,
+                ))?;
+            }
+        }
+
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Write the gap and time headway histograms of every iteration, per lane and for all lanes together,
+    /// to `<name>.gaps.csv`. For time headways `value` is the lower bound of the bin in steps.
+    pub fn write_gap_histograms_to_csv(
+        &self,
+        iteration_infos: &[IterationInfo],
+    ) -> Result<(), WriterError> {
+        let mut csv = self.create_csv(
+            "gaps.csv",
+            &[
+                "iteration",
+                "density",
+                "lane_change_probability",
+                "deceleration_probability",
+                "lane",
+                "quantity",
+                "value",
+                "count",
+            ],
+        )?;
+
+        for i_inf in iteration_infos {
+            let Some(histograms) = &i_inf.gap_histograms else {
+                continue;
+            };
+
+            let mut push_row = |lane: &str, quantity: &str, value: String, count: usize| {
+                csv.serialize((
+                    i_inf.iteration,
+                    i_inf.density,
+                    i_inf.lane_change_probability,
+                    i_inf.deceleration_probability,
+                    lane,
+                    quantity,
+                    value,
+                    count,
+                ))
+            };
+
+            let headway_value =
+                |bin: usize| (bin as f32 * histograms.headway_bin_width).to_string();
+
+            let gaps = histograms
+                .gaps_per_lane
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(lane, h)| (lane.to_string(), h))
+                .chain([("all".to_string(), histograms.gaps_overall())]);
+            for (lane, histogram) in gaps {
+                for (gap, count) in histogram {
+                    push_row(&lane, "gap", gap.to_string(), count)?;
+                }
+            }
+
+            let headways = histograms
+                .headways_per_lane
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(lane, h)| (lane.to_string(), h))
+                .chain([("all".to_string(), histograms.headways_overall())]);
+            for (lane, histogram) in headways {
+                for (bin, count) in histogram {
+                    push_row(&lane, "headway", headway_value(bin), count)?;
+                }
+            }
+        }
+
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Write the travel statistics of every vehicle in every simulation to `<name>.vehicles.csv`
+    pub fn write_vehicle_statistics_to_csv(
+        &self,
+        iteration_infos: &[IterationInfo],
+    ) -> Result<(), WriterError> {
+        let mut csv = self.create_csv(
+            "vehicles.csv",
+            &[
+                "iteration",
+                "density",
+                "simulation",
+                "vehicle_id",
+                "original_lane",
+                "final_lane",
+                "steps",
+                "distance_travelled",
+                "laps",
+                "time_stopped",
+                "lane_changes",
+                "average_speed",
+            ],
+        )?;
+
+        for i_inf in iteration_infos {
+            for (simulation, vehicles) in i_inf.final_vehicles.iter().enumerate() {
+                for vehicle in vehicles {
+                    let statistics = &vehicle.statistics;
+                    csv.serialize((
+                        i_inf.iteration,
+                        i_inf.density,
+                        simulation + 1,
+                        vehicle.id,
+                        vehicle.original_lane,
+                        vehicle.position.y,
+                        statistics.steps,
+                        statistics.distance_travelled,
+                        statistics.laps,
+                        statistics.time_stopped,
+                        statistics.lane_changes,
+                        statistics.average_speed(),
+                    ))?;
+                }
+            }
+        }
+
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Write the Sobol indices of a sensitivity analysis to `<name>.sensitivity.csv`
+    pub fn write_sensitivity_indices_to_csv(
+        &self,
+        indices: &[SensitivityIndex],
+    ) -> Result<(), WriterError> {
+        let mut csv = self.create_csv(
+            "sensitivity.csv",
+            &[
+                "output",
+                "factor",
+                "first_order",
+                "first_order_ci95_low",
+                "first_order_ci95_high",
+                "total_order",
+                "total_order_ci95_low",
+                "total_order_ci95_high",
+            ],
+        )?;
+
+        for index in indices {
+            csv.serialize((
+                index.output,
+                &index.factor,
+                index.first_order,
+                index.first_order_ci95.0,
+                index.first_order_ci95.1,
+                index.total_order,
+                index.total_order_ci95.0,
+                index.total_order_ci95.1,
+            ))?;
+        }
+
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Write every parameter set tried by a calibration and its error to `<name>.calibration.csv`
+    pub fn write_calibration_history_to_csv(
+        &self,
+        candidates: &[CalibrationCandidate],
+    ) -> Result<(), WriterError> {
+        let mut csv = self.create_csv(
+            "calibration.csv",
+            &[
+                "evaluation",
+                "deceleration_probability",
+                "lane_change_probability",
+                "max_speed_lane_1",
+                "max_speed_lane_2",
+                "max_speed_lane_3",
+                "flow_rmse",
+                "speed_rmse",
+                "error",
+            ],
+        )?;
+
+        for candidate in candidates {
+            csv.serialize((
+                candidate.evaluation,
+                candidate.deceleration_probability,
+                candidate.lane_change_probability,
+                candidate.lane_speeds[0],
+                candidate.lane_speeds[1],
+                candidate.lane_speeds[2],
+                candidate.flow_rmse,
+                candidate.speed_rmse,
+                candidate.error,
+            ))?;
         }
+
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Write the best parameter set of a calibration to `<name>.calibration.toml`
+    pub fn write_calibration_result(&self, result: &CalibrationResult) -> Result<(), WriterError> {
+        fs::write(
+            self.sibling_file_path("calibration.toml"),
+            toml::to_string(result)?,
+        )?;
+        Ok(())
+    }
+
+    /// Write the metadata of the run to `<name>.metadata.toml`
+    pub fn write_metadata_to_file(&self, metadata: &MetaData) -> Result<(), WriterError> {
+        let mut file = File::create(self.sibling_file_path("metadata.toml"))?;
+        file.write_all(toml::to_string(metadata)?.as_bytes())?;
+        Ok(())
     }
+}
 
-    pub fn write_metadata_to_file(&self, metadata: &MetaData) {
-        //Remove .csv extension
-        let mut file_path = self.file_path.with_extension("");
-        file_path.set_extension("metadata");
+/// The average speed of an empty lane or road is NaN, it is written as zero
+pub fn nan_to_zero(value: f32) -> f32 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value
+    }
+}
 
-        let mut file = fs::File::create(file_path).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let metadata = format!("Road Length: {}\nNumber of Simulations: {}\nIterations per Simulation: {}\nSimulation Type: {:?}\nSpeeds per lane: {} {} {}",
-            metadata.road_len,
-            metadata.num_simulations,
-            metadata.iterations_per_simulation,
-            metadata.sim_type,
-            metadata.speeds_per_lane[0],
-            metadata.speeds_per_lane[1],
-            metadata.speeds_per_lane[2]
+    #[test]
+    fn sibling_file_path_replaces_the_result_extension() {
+        assert_eq!(
+            sibling_file_path(Path::new("out/a_0.4.csv"), "metadata.toml"),
+            Path::new("out/a_0.4.metadata.toml")
         );
+        assert_eq!(
+            sibling_file_path(Path::new("a.jsonl"), "jams.csv"),
+            Path::new("a.jams.csv")
+        );
+    }
 
-        file.write_all(metadata.as_bytes()).unwrap();
+    #[test]
+    fn sibling_file_path_keeps_other_extensions() {
+        assert_eq!(
+            sibling_file_path(Path::new("out/a_0.4"), "checkpoint"),
+            Path::new("out/a_0.4.checkpoint")
+        );
+        assert_eq!(
+            sibling_file_path(Path::new("results"), "gif"),
+            Path::new("results.gif")
+        );
     }
 }
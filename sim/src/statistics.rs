@@ -0,0 +1,337 @@
+use crate::typedef::{SampleStatistics, TTest};
+
+/// Two-sided 95% critical values of Student's t-distribution for 1 to 30 degrees of freedom
+const T_CRITICAL_95: [f32; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// The two-sided 95% critical value of Student's t-distribution
+/// # Arguments
+/// * `degrees_of_freedom` - The degrees of freedom, must be at least 1
+pub fn t_critical_95(degrees_of_freedom: usize) -> f32 {
+    if degrees_of_freedom == 0 {
+        return f32::NAN;
+    }
+
+    if let Some(t) = T_CRITICAL_95.get(degrees_of_freedom - 1) {
+        return *t;
+    }
+
+    // Cornish-Fisher expansion around the normal quantile, accurate to three decimals above 30 degrees of freedom
+    let z: f32 = 1.959964;
+    let df = degrees_of_freedom as f32;
+    z + (z.powi(3) + z) / (4.0 * df)
+        + (5.0 * z.powi(5) + 16.0 * z.powi(3) + 3.0 * z) / (96.0 * df.powi(2))
+}
+
+/// The two-sided p-value of Student's t-distribution
+/// # Arguments
+/// * `t` - The t statistic
+/// * `degrees_of_freedom` - The degrees of freedom, need not be a whole number
+pub fn t_test_p_value(t: f64, degrees_of_freedom: f64) -> f64 {
+    if !t.is_finite() || degrees_of_freedom <= 0.0 {
+        return f64::NAN;
+    }
+    regularized_incomplete_beta(
+        degrees_of_freedom / (degrees_of_freedom + t * t),
+        degrees_of_freedom / 2.0,
+        0.5,
+    )
+}
+
+/// Welch's t-test of the difference between the means of two sets of samples, `b - a`.
+/// `None` if the spread of either set is unknown or both sets have no spread.
+pub fn welch_t_test(a: &SampleStatistics, b: &SampleStatistics) -> Option<TTest> {
+    if a.samples < 2 || b.samples < 2 {
+        return None;
+    }
+    let (se_a, se_b) = (a.std_err as f64, b.std_err as f64);
+    let variance = se_a.powi(2) + se_b.powi(2);
+    if !variance.is_finite() || variance <= 0.0 {
+        return None;
+    }
+
+    let t = (b.mean - a.mean) as f64 / variance.sqrt();
+    // Welch-Satterthwaite equation
+    let degrees_of_freedom = variance.powi(2)
+        / (se_a.powi(4) / (a.samples - 1) as f64 + se_b.powi(4) / (b.samples - 1) as f64);
+
+    Some(TTest {
+        t: t as f32,
+        degrees_of_freedom: degrees_of_freedom as f32,
+        p_value: t_test_p_value(t, degrees_of_freedom) as f32,
+    })
+}
+
+/// Paired t-test of whether the mean of the differences between paired samples is zero.
+/// `None` with fewer than two differences or if all differences are equal.
+pub fn paired_t_test(differences: &[f32]) -> Option<TTest> {
+    let statistics = SampleStatistics::from_samples(differences.iter().copied());
+    if statistics.samples < 2 || statistics.std_err.is_nan() || statistics.std_err <= 0.0 {
+        return None;
+    }
+
+    let t = statistics.mean as f64 / statistics.std_err as f64;
+    let degrees_of_freedom = (statistics.samples - 1) as f64;
+    Some(TTest {
+        t: t as f32,
+        degrees_of_freedom: degrees_of_freedom as f32,
+        p_value: t_test_p_value(t, degrees_of_freedom) as f32,
+    })
+}
+
+/// The regularized incomplete beta function I_x(a, b), evaluated with its continued fraction
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only below this point, above it the symmetry I_x(a, b) = 1 - I_{1-x}(b, a) is used
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// The continued fraction of the incomplete beta function, by the modified Lentz method
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut fraction = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+
+        for coefficient in [even, odd] {
+            d = 1.0 + coefficient * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + coefficient / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            fraction *= d * c;
+        }
+
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    fraction
+}
+
+/// The natural logarithm of the gamma function, by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+impl SampleStatistics {
+    /// Calculate the statistics of a set of samples.
+    /// Samples that are not finite, such as the average speed of an empty lane, are ignored.
+    /// With fewer than two samples the spread is unknown and reported as NaN.
+    pub fn from_samples(samples: impl IntoIterator<Item = f32>) -> Self {
+        let samples = samples
+            .into_iter()
+            .filter(|s| s.is_finite())
+            .collect::<Vec<_>>();
+        let n = samples.len();
+
+        if n == 0 {
+            return Self {
+                samples: 0,
+                mean: f32::NAN,
+                std_dev: f32::NAN,
+                std_err: f32::NAN,
+                ci95_low: f32::NAN,
+                ci95_high: f32::NAN,
+            };
+        }
+
+        let mean = samples.iter().sum::<f32>() / n as f32;
+
+        if n == 1 {
+            return Self {
+                samples: n,
+                mean,
+                std_dev: f32::NAN,
+                std_err: f32::NAN,
+                ci95_low: f32::NAN,
+                ci95_high: f32::NAN,
+            };
+        }
+
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / (n - 1) as f32;
+        let std_dev = variance.sqrt();
+        let std_err = std_dev / (n as f32).sqrt();
+        let half_width = t_critical_95(n - 1) * std_err;
+
+        Self {
+            samples: n,
+            mean,
+            std_dev,
+            std_err,
+            ci95_low: mean - half_width,
+            ci95_high: mean + half_width,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn t_critical_95_from_the_table() {
+        assert_close(t_critical_95(1), 12.706, 1e-6);
+        assert_close(t_critical_95(10), 2.228, 1e-6);
+        assert_close(t_critical_95(30), 2.042, 1e-6);
+        assert!(t_critical_95(0).is_nan());
+    }
+
+    #[test]
+    fn t_critical_95_above_the_table_follows_cornish_fisher() {
+        // Two-sided 95% critical values of Student's t-distribution
+        for (degrees_of_freedom, expected) in
+            [(31, 2.0395), (40, 2.0211), (60, 2.0003), (120, 1.9799)]
+        {
+            assert_close(t_critical_95(degrees_of_freedom), expected, 1e-3);
+        }
+        assert_close(t_critical_95(100_000), 1.95996, 1e-4);
+    }
+
+    #[test]
+    fn sample_statistics_of_a_known_sample() {
+        let statistics = SampleStatistics::from_samples([2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        let std_dev = (32.0f32 / 7.0).sqrt();
+        let std_err = std_dev / 8.0f32.sqrt();
+
+        assert_eq!(statistics.samples, 8);
+        assert_close(statistics.mean, 5.0, 1e-6);
+        assert_close(statistics.std_dev, std_dev, 1e-5);
+        assert_close(statistics.std_err, std_err, 1e-5);
+        assert_close(statistics.ci95_low, 5.0 - 2.365 * std_err, 1e-5);
+        assert_close(statistics.ci95_high, 5.0 + 2.365 * std_err, 1e-5);
+    }
+
+    #[test]
+    fn sample_statistics_ignore_samples_that_are_not_finite() {
+        let statistics = SampleStatistics::from_samples([1.0, f32::NAN, 3.0, f32::INFINITY]);
+        assert_eq!(statistics.samples, 2);
+        assert_close(statistics.mean, 2.0, 1e-6);
+
+        let single = SampleStatistics::from_samples([1.0]);
+        assert_eq!(single.samples, 1);
+        assert!(single.std_dev.is_nan() && single.ci95_low.is_nan());
+
+        assert!(SampleStatistics::from_samples([]).mean.is_nan());
+    }
+
+    #[test]
+    fn ln_gamma_of_known_values() {
+        assert!(ln_gamma(1.0).abs() < 1e-10);
+        assert!((ln_gamma(5.0) - 24.0f64.ln()).abs() < 1e-10);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-10);
+        assert!((ln_gamma(10.5) - 13.940_625_219_403_76).abs() < 1e-9);
+    }
+
+    #[test]
+    fn regularized_incomplete_beta_of_known_values() {
+        // I_x(a, 1) = x^a and I_x(1, b) = 1 - (1 - x)^b
+        assert!((regularized_incomplete_beta(0.3, 2.5, 1.0) - 0.3f64.powf(2.5)).abs() < 1e-10);
+        assert!(
+            (regularized_incomplete_beta(0.3, 1.0, 4.0) - (1.0 - 0.7f64.powi(4))).abs() < 1e-10
+        );
+        // The binomial sum of I_0.4(2, 3) and the arcsine distribution of I_x(1/2, 1/2)
+        assert!((regularized_incomplete_beta(0.4, 2.0, 3.0) - 0.5248).abs() < 1e-10);
+        let arcsine = 2.0 / std::f64::consts::PI * 0.3f64.sqrt().asin();
+        assert!((regularized_incomplete_beta(0.3, 0.5, 0.5) - arcsine).abs() < 1e-10);
+        // Both sides of the switch to the symmetry I_x(a, b) = 1 - I_{1-x}(b, a)
+        let above = regularized_incomplete_beta(0.8, 3.0, 2.0);
+        assert!((above - (1.0 - regularized_incomplete_beta(0.2, 2.0, 3.0))).abs() < 1e-12);
+        assert_eq!(regularized_incomplete_beta(0.0, 2.0, 3.0), 0.0);
+        assert_eq!(regularized_incomplete_beta(1.0, 2.0, 3.0), 1.0);
+    }
+
+    #[test]
+    fn t_test_p_value_of_known_values() {
+        // The two-sided 95% critical value for 10 degrees of freedom
+        assert!((t_test_p_value(2.228, 10.0) - 0.05).abs() < 1e-4);
+        // Closed forms for 1 and 2 degrees of freedom
+        assert!((t_test_p_value(1.0, 1.0) - 0.5).abs() < 1e-10);
+        assert!((t_test_p_value(-2.0, 2.0) - (1.0 - 2.0 / 6.0f64.sqrt())).abs() < 1e-10);
+        assert!((t_test_p_value(0.0, 7.5) - 1.0).abs() < 1e-12);
+        assert!(t_test_p_value(f64::NAN, 10.0).is_nan());
+        assert!(t_test_p_value(1.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn welch_t_test_of_a_textbook_pair() {
+        // Example 1 of Welch's t-test on Wikipedia: t = 2.46, 25.0 degrees of freedom, p = 0.021
+        let a = SampleStatistics::from_samples([
+            27.5, 21.0, 19.0, 23.6, 17.0, 17.9, 16.9, 20.1, 21.9, 22.6, 23.1, 19.6, 19.0, 21.7,
+            21.4,
+        ]);
+        let b = SampleStatistics::from_samples([
+            27.1, 22.0, 20.8, 23.4, 23.4, 23.5, 25.8, 22.0, 24.8, 20.2, 21.9, 22.1, 22.9, 20.5,
+            24.4,
+        ]);
+
+        let test = welch_t_test(&a, &b).unwrap();
+        assert_close(test.t, 2.4554, 1e-3);
+        assert_close(test.degrees_of_freedom, 24.9885, 1e-2);
+        assert_close(test.p_value, 0.02138, 1e-4);
+
+        let single = SampleStatistics::from_samples([1.0]);
+        assert!(welch_t_test(&single, &b).is_none());
+    }
+
+    #[test]
+    fn paired_t_test_of_known_differences() {
+        let test = paired_t_test(&[1.2, 0.4, -0.3, 1.5, 0.9, 0.8]).unwrap();
+        assert_close(test.t, 2.8939, 1e-3);
+        assert_close(test.degrees_of_freedom, 5.0, 0.0);
+        assert_close(test.p_value, 0.03403, 1e-4);
+
+        assert!(paired_t_test(&[0.5, 0.5, 0.5]).is_none());
+        assert!(paired_t_test(&[0.5]).is_none());
+    }
+}
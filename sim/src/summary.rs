@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use tracing::warn;
+
+use crate::comparison::{capacity, capacity_point};
+use crate::loader::{find_results, load_results};
+use crate::typedef::{
+    IterationInfo, LoadError, LoadedResults, ResultSummary, SweepBranch, WriterError,
+};
+
+impl ResultSummary {
+    /// Summarize one results file
+    /// # Arguments
+    /// * `results` - The loaded results
+    /// * `directory` - The directory the path of the results is made relative to
+    /// * `densities` - The densities at which the average speed is reported
+    pub fn new(results: &LoadedResults, directory: &Path, densities: &[f32]) -> Self {
+        Self {
+            file: results
+                .file_path
+                .strip_prefix(directory)
+                .unwrap_or(&results.file_path)
+                .to_path_buf(),
+            experiment: results.metadata.as_ref().map(|m| m.experiment.clone()),
+            capacity: capacity_point(&results.iteration_infos).map(capacity),
+            speeds_at_densities: densities
+                .iter()
+                .map(|density| {
+                    (
+                        *density,
+                        speed_at_density(&results.iteration_infos, *density),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Summarize every results file below a directory.
+/// Files that cannot be read, like csv files that are not results, are skipped with a warning.
+/// # Arguments
+/// * `directory` - The directory to search for results
+/// * `densities` - The densities at which the average speed is reported
+pub fn summarize_directory(
+    directory: &Path,
+    densities: &[f32],
+) -> Result<Vec<ResultSummary>, LoadError> {
+    Ok(find_results(directory)?
+        .iter()
+        .filter_map(|file_path| match load_results(file_path) {
+            Ok(results) => Some(ResultSummary::new(&results, directory, densities)),
+            Err(e) => {
+                warn!("Skipping {}: {}", file_path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Write the summaries to a csv file with one row per results file:
+/// the parameters of the run, the capacity, the critical density and the speed at every requested density.
+/// The parameters are empty for results without metadata.
+/// # Arguments
+/// * `summaries` - The summaries, all with the same densities
+/// * `file_path` - The path of the csv file
+pub fn write_summary_table(
+    summaries: &[ResultSummary],
+    file_path: &Path,
+) -> Result<(), WriterError> {
+    let mut csv = csv::Writer::from_path(file_path)?;
+
+    let mut header = [
+        "file",
+        "parameter",
+        "repetitions",
+        "iterations",
+        "road_length",
+        "max_speed_lane_1",
+        "max_speed_lane_2",
+        "max_speed_lane_3",
+        "deceleration_probability",
+        "lane_change_probability",
+        "seed",
+        "capacity",
+        "critical_density",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect::<Vec<_>>();
+    if let Some(summary) = summaries.first() {
+        header.extend(
+            summary
+                .speeds_at_densities
+                .iter()
+                .map(|(density, _)| format!("average_speed_at_{density}")),
+        );
+    }
+    csv.write_record(&header)?;
+
+    for summary in summaries {
+        let mut record = vec![summary.file.display().to_string()];
+        match &summary.experiment {
+            Some(experiment) => {
+                let lane_speed = |lane: usize| {
+                    experiment
+                        .road
+                        .lane_speeds
+                        .get(lane)
+                        .map(|speed| speed.to_string())
+                        .unwrap_or_default()
+                };
+                record.extend([
+                    experiment.sweep.parameter_name().to_string(),
+                    experiment.repetitions.to_string(),
+                    experiment.iterations.to_string(),
+                    experiment.road.length.to_string(),
+                    lane_speed(0),
+                    lane_speed(1),
+                    lane_speed(2),
+                    experiment.probabilities.deceleration.to_string(),
+                    experiment.probabilities.lane_change.to_string(),
+                    experiment
+                        .seed
+                        .map(|seed| seed.to_string())
+                        .unwrap_or_default(),
+                ]);
+            }
+            None => record.extend(std::iter::repeat_n(String::new(), 10)),
+        }
+        match summary.capacity {
+            Some(capacity) => {
+                record.extend([capacity.flow.to_string(), capacity.density.to_string()])
+            }
+            None => record.extend([String::new(), String::new()]),
+        }
+        record.extend(
+            summary
+                .speeds_at_densities
+                .iter()
+                .map(|(_, speed)| speed.to_string()),
+        );
+        csv.write_record(&record)?;
+    }
+
+    csv.flush()?;
+    Ok(())
+}
+
+/// The average speed at a density, linearly interpolated between the two nearest simulated densities.
+/// Of a hysteresis sweep only the way up is used. NaN outside of the simulated densities.
+fn speed_at_density(iteration_infos: &[IterationInfo], density: f32) -> f32 {
+    let mut points = iteration_infos
+        .iter()
+        .filter(|info| info.branch != Some(SweepBranch::Down))
+        .map(|info| (info.density, info.average_speed))
+        .collect::<Vec<_>>();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    points
+        .windows(2)
+        .find(|pair| pair[0].0 <= density && density <= pair[1].0)
+        .map(|pair| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if x1 - x0 <= f32::EPSILON {
+                return y0;
+            }
+            y0 + (y1 - y0) * (density - x0) / (x1 - x0)
+        })
+        .or_else(|| {
+            points
+                .iter()
+                .find(|(x, _)| (x - density).abs() <= f32::EPSILON)
+                .map(|(_, y)| *y)
+        })
+        .unwrap_or(f32::NAN)
+}
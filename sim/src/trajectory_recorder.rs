@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::typedef::{Road, TrajectoryFormat, TrajectoryRecorder, WriterError};
+
+const CSV_DELIMITER: &str = ",";
+/// The size of a binary record: simulation, iteration, step and vehicle id as u32, lane, x and velocity as u8
+const BINARY_RECORD_SIZE: usize = 19;
+
+impl TrajectoryRecorder {
+    /// Create the trajectory file, writing the header if the format has one
+    /// # Arguments
+    /// * `file_path` - The file to write the trajectories to
+    /// * `format` - The format of the records
+    /// * `steps` - The steps to record, step 0 is the initial state of the road
+    /// * `vehicle_ids` - The vehicles to record, or `None` to record all vehicles
+    /// * `completed_points` - The points completed by a resumed run, `None` to start a new file.
+    ///   The records of these points are kept, the records of points that did not complete are removed.
+    pub fn new(
+        file_path: &Path,
+        format: TrajectoryFormat,
+        steps: Range<usize>,
+        vehicle_ids: Option<HashSet<usize>>,
+        completed_points: Option<HashSet<(usize, usize)>>,
+    ) -> Result<Self, WriterError> {
+        let kept = match &completed_points {
+            Some(completed) if file_path.exists() => {
+                completed_records(&fs::read(file_path)?, format, completed)
+            }
+            _ => Vec::new(),
+        };
+
+        let mut writer = BufWriter::new(fs::File::create(file_path)?);
+        if format == TrajectoryFormat::Csv {
+            let header = format!(
+                "simulation{d}iteration{d}step{d}vehicle_id{d}lane{d}x{d}velocity\n",
+                d = CSV_DELIMITER
+            );
+            writer.write_all(header.as_bytes())?;
+        }
+        writer.write_all(&kept)?;
+        writer.flush()?;
+
+        Ok(Self {
+            format,
+            steps,
+            vehicle_ids,
+            recorded_points: completed_points.unwrap_or_default(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Write the state of the selected vehicles, if the step is within the selected steps
+    /// # Arguments
+    /// * `simulation` - The number of the simulation, starting at 0
+    /// * `iteration` - The number of the set of parameters within the simulation
+    /// * `step` - The step the road is in
+    /// * `road` - The road after the step
+    pub fn record(
+        &self,
+        simulation: usize,
+        iteration: usize,
+        step: usize,
+        road: &Road,
+    ) -> Result<(), WriterError> {
+        if !self.steps.contains(&step) || self.recorded_points.contains(&(simulation, iteration)) {
+            return Ok(());
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+
+        let mut vehicles = road
+            .vehicles
+            .iter()
+            .filter(|v| {
+                self.vehicle_ids
+                    .as_ref()
+                    .is_none_or(|ids| ids.contains(&v.id))
+            })
+            .collect::<Vec<_>>();
+        vehicles.sort_by_key(|v| v.id);
+
+        for vehicle in vehicles {
+            match self.format {
+                TrajectoryFormat::Csv => {
+                    let record = format!(
+                        "{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}\n",
+                        simulation,
+                        iteration,
+                        step,
+                        vehicle.id,
+                        vehicle.position.y,
+                        vehicle.position.x,
+                        vehicle.velocity.into_inner(),
+                        d = CSV_DELIMITER,
+                    );
+                    writer.write_all(record.as_bytes())?;
+                }
+                TrajectoryFormat::Binary => {
+                    let mut record = [0u8; BINARY_RECORD_SIZE];
+                    record[0..4].copy_from_slice(&(simulation as u32).to_le_bytes());
+                    record[4..8].copy_from_slice(&(iteration as u32).to_le_bytes());
+                    record[8..12].copy_from_slice(&(step as u32).to_le_bytes());
+                    record[12..16].copy_from_slice(&(vehicle.id as u32).to_le_bytes());
+                    record[16] = vehicle.position.y;
+                    record[17] = vehicle.position.x;
+                    record[18] = vehicle.velocity.into_inner();
+                    writer.write_all(&record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<(), WriterError> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+/// The records of a trajectory file that belong to completed points, without the csv header.
+/// A record cut off by the end of the previous run is dropped.
+fn completed_records(
+    contents: &[u8],
+    format: TrajectoryFormat,
+    completed: &HashSet<(usize, usize)>,
+) -> Vec<u8> {
+    match format {
+        TrajectoryFormat::Csv => {
+            let mut kept = Vec::new();
+            for line in contents.split_inclusive(|b| *b == b'\n').skip(1) {
+                let mut fields = std::str::from_utf8(line)
+                    .unwrap_or_default()
+                    .split(CSV_DELIMITER)
+                    .map(|field| field.parse::<usize>().ok());
+                let point = (fields.next().flatten(), fields.next().flatten());
+                if let (Some(simulation), Some(iteration)) = point {
+                    if line.ends_with(b"\n") && completed.contains(&(simulation, iteration)) {
+                        kept.extend_from_slice(line);
+                    }
+                }
+            }
+            kept
+        }
+        TrajectoryFormat::Binary => contents
+            .chunks_exact(BINARY_RECORD_SIZE)
+            .filter(|record| {
+                let field = |range: Range<usize>| {
+                    u32::from_le_bytes(record[range].try_into().unwrap()) as usize
+                };
+                completed.contains(&(field(0..4), field(4..8)))
+            })
+            .flatten()
+            .copied()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completed_records_keep_only_completed_points() {
+        let contents = b"simulation,iteration,step,vehicle_id,lane,x,velocity\n\
+0,1,0,0,0,3,1\n\
+0,2,0,0,0,3,1\n\
+1,1,0,0,0,3,1\n\
+0,1,1,0,0,4";
+        let completed = HashSet::from([(0, 1), (1, 1)]);
+
+        let kept = completed_records(contents, TrajectoryFormat::Csv, &completed);
+        assert_eq!(kept, b"0,1,0,0,0,3,1\n1,1,0,0,0,3,1\n");
+    }
+
+    #[test]
+    fn completed_binary_records_drop_a_cut_off_record() {
+        let record = |simulation: u32, iteration: u32| {
+            let mut record = [0u8; BINARY_RECORD_SIZE];
+            record[0..4].copy_from_slice(&simulation.to_le_bytes());
+            record[4..8].copy_from_slice(&iteration.to_le_bytes());
+            record
+        };
+        let mut contents = [record(0, 1), record(0, 2), record(1, 1)].concat();
+        contents.extend_from_slice(&record(0, 1)[..5]);
+
+        let kept = completed_records(
+            &contents,
+            TrajectoryFormat::Binary,
+            &HashSet::from([(0, 1)]),
+        );
+        assert_eq!(kept, record(0, 1));
+    }
+}
@@ -1,15 +1,27 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 use std::{
     ops::{AddAssign, Deref, SubAssign},
     time::Duration,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Road {
     pub len: u8,
     pub deceleration_probability: f32,
     pub vehicles: Vec<Vehicle>,
     pub speed_per_lane: Vec<Velocity>,
+    pub lane_change_counts: LaneChangeCounts,
+    /// Seed from which the random numbers of every vehicle in every step are derived
+    pub seed: u64,
+    /// The number of steps the road has been updated
+    pub steps: u64,
 }
 
 impl SubAssign<u32> for Velocity {
@@ -18,16 +30,72 @@ impl SubAssign<u32> for Velocity {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vehicle {
+    /// Stable identifier of the vehicle, unique within a road
+    pub id: usize,
     pub original_lane: u8,
     pub position: Position,
     pub velocity: Velocity,
     pub move_left_chance: f32,
     pub move_right_chance: f32,
+    /// Lane changes made or rejected by this vehicle during the last step
+    pub lane_change_events: Vec<LaneChangeEvent>,
+    pub statistics: VehicleStatistics,
+}
+
+/// Per-vehicle travel statistics, accumulated over all steps the vehicle has driven
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VehicleStatistics {
+    pub steps: usize,
+    /// The number of cells driven
+    pub distance_travelled: usize,
+    /// The number of times the vehicle has driven the full length of the road
+    pub laps: usize,
+    /// The number of steps the vehicle was standing still
+    pub time_stopped: usize,
+    pub lane_changes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LaneChangeDirection {
+    /// Towards the lane with the higher index
+    Left,
+    /// Towards the lane with the lower index
+    Right,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LaneChangeEvent {
+    /// The vehicle changed lanes
+    Changed {
+        direction: LaneChangeDirection,
+        from_lane: u8,
+    },
+    /// The vehicle wanted to change lanes, but the safety check rejected it
+    Unsafe {
+        direction: LaneChangeDirection,
+        from_lane: u8,
+    },
+}
+
+/// Lane change events counted over all steps of a simulation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaneChangeCounts {
+    pub left: usize,
+    pub right: usize,
+    /// Lane changes per source lane
+    pub from_lane: Vec<usize>,
+    pub unsafe_left: usize,
+    pub unsafe_right: usize,
+    /// The number of steps counted
+    #[serde(default)]
+    pub steps: usize,
+    /// The sum of the number of vehicles on the road over all steps
+    pub vehicle_steps: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: u8,
     pub y: u8,
@@ -50,11 +118,11 @@ impl Position {
     /// * self - The first position
     /// * rhs - The second position
     pub fn distance_1d(&self, rhs: &Self) -> u8 {
-        (self.x as i8 - rhs.x as i8).abs() as u8
+        (self.x as i8 - rhs.x as i8).unsigned_abs()
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Velocity(u8);
 
 impl AddAssign for Velocity {
@@ -81,7 +149,7 @@ impl Deref for Velocity {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IterationInfo {
     pub iteration: usize,
     pub time: Duration,
@@ -93,18 +161,192 @@ pub struct IterationInfo {
     pub deceleration_probability: f32,
     pub max_speed_per_lane: Vec<u8>,
     pub flow: f32,
+    pub road_length: usize,
+    pub lane_changes_left: f32,
+    pub lane_changes_right: f32,
+    pub lane_changes_from_lane: Vec<f32>,
+    pub unsafe_lane_changes: f32,
+    /// Average number of lane changes per step
+    pub lane_changes_per_step: f32,
+    /// Average number of lane changes per vehicle per step
+    pub lane_change_rate: f32,
+    /// The direction of the density sweep in a hysteresis simulation
+    pub branch: Option<SweepBranch>,
+    /// The spread of every averaged metric over the simulations, keyed by the csv column of the metric
+    pub metric_statistics: Vec<(String, SampleStatistics)>,
+    pub jam_statistics: Option<JamStatistics>,
+    pub gap_histograms: Option<GapHistograms>,
+    /// The number of lane changes in every step, only filled when lane changes per step are recorded
+    pub lane_changes_by_step: Option<Vec<f32>>,
+    /// The vehicles at the end of every simulation, only filled when vehicle statistics are recorded
+    pub final_vehicles: Vec<Vec<Vehicle>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SweepBranch {
+    /// The density is increasing
+    Up,
+    /// The density is decreasing
+    Down,
+}
+
+/// Summary statistics of one metric over a number of simulations
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SampleStatistics {
+    pub samples: usize,
+    pub mean: f32,
+    /// Sample standard deviation
+    pub std_dev: f32,
+    /// Standard error of the mean
+    pub std_err: f32,
+    /// Lower bound of the 95% confidence interval of the mean
+    pub ci95_low: f32,
+    /// Upper bound of the 95% confidence interval of the mean
+    pub ci95_high: f32,
+}
+
+/// Histograms of the gaps and time headways between vehicles and the vehicle in front of them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GapHistograms {
+    /// Per lane: the number of empty cells in front of a vehicle -> number of occurrences
+    pub gaps_per_lane: Vec<BTreeMap<u8, usize>>,
+    /// Per lane: the time headway bin -> number of occurrences.
+    /// Bin `i` contains the headways from `i * headway_bin_width` up to `(i + 1) * headway_bin_width` steps.
+    pub headways_per_lane: Vec<BTreeMap<usize, usize>>,
+    pub headway_bin_width: f32,
+}
+
+/// Parameters and state used to follow jams through a single simulation
+#[derive(Debug, Clone)]
+pub struct JamTracker {
+    /// Vehicles driving at or below this speed are considered slow
+    pub max_jam_velocity: u8,
+    /// Slow vehicles at most this many cells apart belong to the same jam
+    pub max_jam_gap: u8,
+    /// Clusters with fewer vehicles than this are ignored
+    pub min_jam_size: usize,
+    pub active_jams: Vec<Jam>,
+    pub finished_jams: Vec<Jam>,
+    pub next_jam_id: usize,
+}
+
+/// A cluster of slow vehicles in one lane at one step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JamCluster {
+    pub lane: u8,
+    /// The x position of the most upstream vehicle in the cluster
+    pub upstream_front: u8,
+    /// The number of cells covered from the upstream to the downstream front
+    pub length: u8,
+    pub size: usize,
+}
+
+/// A jam followed over time
+#[derive(Debug, Clone)]
+pub struct Jam {
+    pub id: usize,
+    pub lane: u8,
+    pub birth: usize,
+    pub last_seen: usize,
+    pub dissolved: bool,
+    pub sizes: Vec<usize>,
+    pub upstream_fronts: Vec<u8>,
+    pub last_cluster: JamCluster,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JamStatistics {
+    pub jam_count: usize,
+    pub dissolved_count: usize,
+    /// Peak jam size (in vehicles) -> number of jams
+    pub size_distribution: BTreeMap<usize, usize>,
+    /// Jam lifetime (in steps) -> number of jams
+    pub lifetime_distribution: BTreeMap<usize, usize>,
+    pub wave_velocity_sum: f32,
+    pub wave_velocity_samples: usize,
+}
+
+/// Everything needed to understand and repeat a run, written next to the results as `<name>.metadata.toml`.
+/// Metadata read from the legacy free text format lacks the provenance of the run, which is then `None`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct MetaData {
-    pub road_len: u8,
-    pub num_simulations: usize,
-    pub iterations_per_simulation: usize,
-    pub sim_type: SimulationType,
-    pub speeds_per_lane: Vec<u8>,
+    /// The version of the simulation that produced the results
+    pub crate_version: Option<String>,
+    /// When the run started, in RFC 3339 format
+    pub started: Option<String>,
+    /// The number of threads the vehicles were updated on
+    pub threads: Option<usize>,
+    /// How long the run took, in seconds
+    pub wall_time: Option<f64>,
+    /// Whether the run was stopped before every parameter point was simulated
+    #[serde(default)]
+    pub interrupted: bool,
+    pub seed: Option<u64>,
+    /// A short description of the sweep
+    pub simulation_type: String,
+    /// The fully resolved experiment that was run, with every input parameter
+    pub experiment: ExperimentConfig,
+}
+
+/// The results of a run read back from its files
+#[derive(Debug, Clone)]
+pub struct LoadedResults {
+    pub file_path: PathBuf,
+    pub iteration_infos: Vec<IterationInfo>,
+    /// `None` if there is no metadata next to the results
+    pub metadata: Option<MetaData>,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Failed to read results: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse csv results: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Failed to parse JSON results: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to read metadata: {0}")]
+    Metadata(#[from] ExperimentError),
+    #[error("Invalid results: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlotError {
+    #[error("Failed to create the output directory: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to draw the plot: {0}")]
+    Drawing(String),
+    #[error("Nothing to plot: {0}")]
+    Empty(String),
+}
+
+/// Writes the results of a run: the averaged results of every parameter point in its format,
+/// and every other output next to it in files with their own extension
 pub struct SimulationWriter {
     pub file_path: PathBuf,
+    pub format: ResultFormat,
+}
+
+/// Writes the averaged results of every parameter point, one row per point
+pub trait ResultWriter {
+    /// Start the results, the spread over the simulations follows for every metric in `metric_names`
+    fn write_header(&mut self, metric_names: &[String]) -> Result<(), WriterError>;
+    fn write_row(&mut self, iteration_info: &IterationInfo) -> Result<(), WriterError>;
+    /// Flush the rows written so far
+    fn finish(&mut self) -> Result<(), WriterError>;
+}
+
+/// Comma separated rows with a header, to a file or to the terminal
+pub struct CsvResultWriter<W: std::io::Write> {
+    pub writer: csv::Writer<W>,
+}
+
+/// One JSON object per row, keyed by the column names of the csv format
+pub struct JsonLinesResultWriter<W: std::io::Write> {
+    pub writer: W,
+    pub columns: Vec<String>,
 }
 
 #[derive(Clone)]
@@ -112,6 +354,239 @@ pub enum SimulationType {
     Density(f32, f32, f32),
     LaneChange(f32, f32, f32),
     Deceleration(f32, f32, f32),
+    /// Sweep the density up from start to end and back down again,
+    /// starting every density from the final state of the previous one
+    Hysteresis(f32, f32, f32),
+    /// Sweep the maximum speed of one lane from start up to and including end,
+    /// the other lanes keep their speed
+    LaneSpeed(usize, u8, u8),
+    /// Sweep the difference in maximum speed between neighbouring lanes from start up to and including end.
+    /// The leftmost lane keeps its speed, every other lane is that much slower than the lane to its left.
+    SpeedDifferential(u8, u8),
+    /// Sample the density between start and end, including both, with a budget of points.
+    /// Points are added where the flow or its spread over the simulations changes fastest.
+    AdaptiveDensity(f32, f32, usize),
+    /// Every combination of the values of several parameters
+    Grid(ParameterGrid),
+    /// Random parameter sets of a Sobol sensitivity analysis
+    Sensitivity(SensitivityDesign),
+    /// Search the parameters that best reproduce an observed fundamental diagram
+    Calibration(CalibrationSearch),
+}
+
+/// The values of every parameter in a grid sweep, every combination of them is simulated
+#[derive(Debug, Clone)]
+pub struct ParameterGrid {
+    pub densities: Vec<f32>,
+    pub deceleration_probabilities: Vec<f32>,
+    pub lane_change_probabilities: Vec<f32>,
+    pub lane_speeds: Vec<Vec<u8>>,
+    pub road_lengths: Vec<usize>,
+}
+
+/// The parameters varied by a Sobol sensitivity analysis and the number of base samples.
+/// Every base sample takes two random parameter sets A and B, and for every factor the set A with that factor from B,
+/// so `samples * (factors + 2)` parameter sets are simulated.
+#[derive(Debug, Clone)]
+pub struct SensitivityDesign {
+    pub samples: usize,
+    pub factors: Vec<SensitivityFactor>,
+}
+
+/// A parameter varied by a sensitivity analysis, uniformly between a lower and an upper bound
+#[derive(Debug, Clone, PartialEq)]
+pub enum SensitivityFactor {
+    Density(f32, f32),
+    DecelerationProbability(f32, f32),
+    LaneChangeProbability(f32, f32),
+    /// The maximum speed of one lane, every speed from the lower up to and including the upper bound is equally likely
+    LaneSpeed(usize, u8, u8),
+}
+
+/// The first and total order Sobol indices of one factor for one output of the model
+#[derive(Debug, Clone)]
+pub struct SensitivityIndex {
+    /// The name of the output, `flow` or `average_speed`
+    pub output: &'static str,
+    /// The name of the factor, as in the experiment file
+    pub factor: String,
+    /// The fraction of the variance of the output caused by the factor alone
+    pub first_order: f32,
+    /// The 95% bootstrap confidence interval of the first order index
+    pub first_order_ci95: (f32, f32),
+    /// The fraction of the variance of the output caused by the factor, including its interactions with other factors
+    pub total_order: f32,
+    /// The 95% bootstrap confidence interval of the total order index
+    pub total_order_ci95: (f32, f32),
+}
+
+/// The bounds of the parameters searched by a calibration.
+/// Every lane speed is searched on its own between the same bounds.
+#[derive(Debug, Clone)]
+pub struct CalibrationSearch {
+    pub observations: PathBuf,
+    pub max_evaluations: usize,
+    pub deceleration_probability: (f32, f32),
+    pub lane_change_probability: (f32, f32),
+    pub lane_speed: (u8, u8),
+}
+
+/// A point of an observed fundamental diagram, in the units of the simulation:
+/// vehicles per cell, vehicles per cell and step, and cells per step
+#[derive(Debug, Clone, Deserialize)]
+pub struct ObservedPoint {
+    pub density: f32,
+    pub flow: f32,
+    #[serde(default, alias = "average_speed")]
+    pub speed: Option<f32>,
+}
+
+/// A parameter set tried by a calibration and how far its fundamental diagram is from the observed one
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationCandidate {
+    /// The number of the parameter set within the search, starting at 1
+    pub evaluation: usize,
+    pub deceleration_probability: f32,
+    pub lane_change_probability: f32,
+    pub lane_speeds: Vec<u8>,
+    /// The root mean square difference between the simulated and the observed flow
+    pub flow_rmse: f32,
+    /// The root mean square difference between the simulated and the observed speed,
+    /// `None` if the observations have no speeds
+    pub speed_rmse: Option<f32>,
+    /// The minimised error: the flow and speed RMSEs divided by the mean observed flow and speed, added together
+    pub error: f32,
+}
+
+/// The outcome of a calibration, written to `<name>.calibration.toml`
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationResult {
+    /// The number of parameter sets that were simulated
+    pub evaluations: usize,
+    /// Whether the search was stopped before it converged or ran out of evaluations
+    pub interrupted: bool,
+    pub best: CalibrationCandidate,
+}
+
+/// The outcome of a two-sided Student's t-test
+#[derive(Debug, Clone, Copy)]
+pub struct TTest {
+    pub t: f32,
+    pub degrees_of_freedom: f32,
+    /// The probability of a difference at least this large if there is no difference
+    pub p_value: f32,
+}
+
+/// One metric of two runs at the same parameter point
+#[derive(Debug, Clone, Copy)]
+pub struct MetricComparison {
+    pub a: f32,
+    pub b: f32,
+    /// `b - a`
+    pub difference: f32,
+    /// Welch's t-test of the difference, only known if the spread over the simulations was written
+    pub test: Option<TTest>,
+}
+
+/// The flow and speed of two runs at one parameter point
+#[derive(Debug, Clone)]
+pub struct PointComparison {
+    pub density: f32,
+    pub deceleration_probability: f32,
+    pub lane_change_probability: f32,
+    pub branch: Option<SweepBranch>,
+    pub flow: MetricComparison,
+    pub average_speed: MetricComparison,
+}
+
+/// The highest flow of a run and the density at which it is reached
+#[derive(Debug, Clone, Copy)]
+pub struct Capacity {
+    pub density: f32,
+    pub flow: f32,
+}
+
+/// The differences between two runs, `b` compared to `a`
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// The parameter points simulated in both runs
+    pub points: Vec<PointComparison>,
+    /// The number of parameter points of `a` and `b` without a match in the other run
+    pub unmatched: (usize, usize),
+    /// Paired t-test of the flow differences over all points
+    pub flow_test: Option<TTest>,
+    /// Paired t-test of the average speed differences over all points
+    pub average_speed_test: Option<TTest>,
+    pub capacity_a: Option<Capacity>,
+    pub capacity_b: Option<Capacity>,
+    /// Welch's t-test of the difference between the capacities
+    pub capacity_test: Option<TTest>,
+}
+
+/// The summary metrics of one results file, a row of the summary table of a results directory
+#[derive(Debug, Clone)]
+pub struct ResultSummary {
+    /// The path of the results, relative to the summarized directory
+    pub file: PathBuf,
+    /// The experiment from the metadata, `None` if the results have no metadata
+    pub experiment: Option<ExperimentConfig>,
+    pub capacity: Option<Capacity>,
+    /// The average speed at every requested density, interpolated between the simulated densities.
+    /// NaN outside of the simulated densities.
+    pub speeds_at_densities: Vec<(f32, f32)>,
+}
+
+/// How the vehicles of a new road are placed and how fast they drive
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InitialCondition {
+    #[serde(default)]
+    pub placement: VehiclePlacement,
+    #[serde(default)]
+    pub speeds: InitialSpeeds,
+}
+
+/// Where the vehicles of a new road are placed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum VehiclePlacement {
+    /// Every vehicle in a random free cell of a random lane
+    #[default]
+    Random,
+    /// The vehicles are spread evenly over every lane
+    Uniform,
+    /// The vehicles are packed bumper to bumper in one block at the start of every lane
+    Megajam,
+    /// Every vehicle in a random free cell, with a separate density for every lane.
+    /// The density of the road is ignored.
+    PerLane { densities: Vec<f32> },
+}
+
+/// The speeds of the vehicles of a new road
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum InitialSpeeds {
+    /// A uniformly random speed between 1 and the maximum speed of the lane
+    #[default]
+    Random,
+    Stopped,
+    /// The maximum speed of the lane
+    Max,
+    /// Speed `v` is drawn with a probability proportional to `weights[v]`,
+    /// speeds above the maximum speed of the lane are lowered to that maximum
+    Distribution {
+        weights: Vec<f32>,
+    },
+}
+
+/// One set of parameters with which a road is created
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterPoint {
+    pub density: f32,
+    pub deceleration_probability: f32,
+    pub lane_change_probability: f32,
+    pub lane_speeds: Vec<u8>,
+    pub road_length: usize,
 }
 
 //Determine how to print the simulation type to file
@@ -127,6 +602,52 @@ impl std::fmt::Debug for SimulationType {
             SimulationType::Deceleration(start, end, step) => {
                 write!(f, "Deceleration: {} to {} by {}", start, end, step)
             }
+            SimulationType::Hysteresis(start, end, step) => {
+                write!(f, "Hysteresis: {} to {} and back by {}", start, end, step)
+            }
+            SimulationType::LaneSpeed(lane, start, end) => {
+                write!(f, "Lane Speed: lane {} from {} to {}", lane, start, end)
+            }
+            SimulationType::SpeedDifferential(start, end) => {
+                write!(f, "Speed Differential: {} to {}", start, end)
+            }
+            SimulationType::AdaptiveDensity(start, end, budget) => {
+                write!(
+                    f,
+                    "Adaptive Density: {} to {} with {} points",
+                    start, end, budget
+                )
+            }
+            SimulationType::Grid(grid) => {
+                write!(
+                    f,
+                    "Grid: density {:?} x deceleration {:?} x lane change {:?} x lane speeds {:?} x road length {:?}",
+                    grid.densities,
+                    grid.deceleration_probabilities,
+                    grid.lane_change_probabilities,
+                    grid.lane_speeds,
+                    grid.road_lengths
+                )
+            }
+            SimulationType::Sensitivity(design) => {
+                write!(f, "Sensitivity: {} samples of", design.samples)?;
+                for factor in &design.factors {
+                    let (low, high) = factor.bounds();
+                    write!(f, " {} [{}, {}]", factor.name(), low, high)?;
+                }
+                Ok(())
+            }
+            SimulationType::Calibration(search) => {
+                write!(
+                    f,
+                    "Calibration: {} with at most {} parameter sets, deceleration {:?}, lane change {:?}, lane speeds {:?}",
+                    search.observations.display(),
+                    search.max_evaluations,
+                    search.deceleration_probability,
+                    search.lane_change_probability,
+                    search.lane_speed
+                )
+            }
         }
     }
 }
@@ -141,4 +662,435 @@ pub struct SimulationsHandler {
     pub verbose: bool,
     pub lane_speeds: Vec<u8>,
     pub pretty_print: bool,
+    pub road_length: usize,
+    /// The density used when the density is not the parameter under test
+    pub standard_density: f32,
+    /// Seed from which the random numbers of every simulation are derived
+    pub seed: u64,
+    pub track_jams: bool,
+    pub record_vehicle_statistics: bool,
+    pub trajectory_recorder: Option<TrajectoryRecorder>,
+    pub animation_recorder: Option<AnimationRecorder>,
+    pub record_gap_histograms: bool,
+    pub record_lane_change_steps: bool,
+    /// Steps at the start of every simulation that are not included in the gap and headway histograms
+    pub warmup_steps: usize,
+    pub checkpoint: Option<Checkpoint>,
+    /// How the vehicles are placed on a new road and how fast they drive
+    pub initial_condition: InitialCondition,
+    /// The road every simulation starts from instead of a random road
+    pub initial_state: Option<Road>,
+    /// Save the state of the road after this step of every simulation
+    pub dump_state_at: Option<usize>,
+    pub state_format: StateFormat,
+    /// Show a progress bar while the parameter points are simulated
+    pub show_progress: bool,
+    /// Set to stop the run after the parameter point that is being simulated
+    pub interrupted: Arc<AtomicBool>,
+    /// The observed fundamental diagram a calibration is fitted to, empty for other sweeps
+    pub observations: Vec<ObservedPoint>,
+    /// Every parameter set tried by a calibration, in the order in which they were simulated
+    pub calibration_history: Mutex<Vec<CalibrationCandidate>>,
+    /// The first error of writing the trajectories, the animation or a road state, after which nothing more is recorded
+    pub output_error: Mutex<Option<ExperimentError>>,
+}
+
+/// The format in which the state of a road is saved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateFormat {
+    /// Human readable, written to `.json` files
+    #[default]
+    Json,
+    /// Compact bincode encoding, written to `.bin` files
+    Binary,
+}
+
+/// The format of the averaged results of every parameter point
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultFormat {
+    /// A `.csv` file with a header
+    #[default]
+    Csv,
+    /// A `.jsonl` file with one JSON object per parameter point
+    JsonLines,
+    /// Csv printed to the terminal, the other outputs are still written to files
+    Stdout,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WriterError {
+    #[error("Failed to write results: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to write csv results: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Failed to write JSON results: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to write TOML results: {0}")]
+    Toml(#[from] toml::ser::Error),
+    #[error("Failed to write GIF animation: {0}")]
+    Gif(#[from] gif::EncodingError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RoadStateError {
+    #[error("Failed to read or write road state: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to encode or decode JSON road state: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to encode or decode binary road state: {0}")]
+    Binary(#[from] bincode::Error),
+    #[error("Invalid road state: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrajectoryFormat {
+    /// One comma separated line per record, with a header
+    Csv,
+    /// Fixed size little-endian records without a header:
+    /// simulation (u32), iteration (u32), step (u32), vehicle id (u32), lane (u8), x (u8), velocity (u8)
+    Binary,
+}
+
+/// The parameter points completed during a run, appended to `<name>.checkpoint` as they finish,
+/// so an interrupted run can be resumed. The file starts with the resolved experiment,
+/// which contains the seed from which all random numbers of the run are derived.
+pub struct Checkpoint {
+    /// The points completed by previous runs, keyed by simulation and iteration
+    pub completed: HashMap<(usize, usize), IterationInfo>,
+    pub file_path: PathBuf,
+    pub writer: Mutex<BufWriter<File>>,
+}
+
+/// One completed parameter point in a checkpoint file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointRecord {
+    pub simulation: usize,
+    pub iteration: usize,
+    pub info: IterationInfo,
+}
+
+/// Writes the state of selected vehicles at selected steps to a file
+pub struct TrajectoryRecorder {
+    pub format: TrajectoryFormat,
+    /// The steps to record, step 0 is the initial state of the road
+    pub steps: Range<usize>,
+    /// The ids of the vehicles to record, all vehicles are recorded when `None`
+    pub vehicle_ids: Option<HashSet<usize>>,
+    /// The points recorded by a resumed run, which are not recorded again when they are simulated again
+    pub recorded_points: HashSet<(usize, usize)>,
+    pub writer: Mutex<BufWriter<File>>,
+}
+
+/// The format of a recorded animation of the road
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimationFormat {
+    /// An asciinema `.cast` file, played back in the terminal with the colours of `--pretty-print`
+    Cast,
+    /// An animated `.gif` image
+    Gif,
+}
+
+/// The road at one step of a recorded animation
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub step: usize,
+    /// The cells of every lane, with the velocity and the original lane of the vehicle in the cell
+    pub lanes: Vec<Vec<Option<(u8, u8)>>>,
+    /// The maximum speed of every lane
+    pub lane_speeds: Vec<u8>,
+    pub average_speed: f32,
+}
+
+/// Renders every step of one simulation of one parameter point to an animation,
+/// which is written once the simulation has finished
+pub struct AnimationRecorder {
+    pub format: AnimationFormat,
+    pub file_path: PathBuf,
+    /// The simulation that is recorded, starting at 0
+    pub simulation: usize,
+    /// The parameter point that is recorded, starting at 1
+    pub iteration: usize,
+    /// How long every step is shown
+    pub frame_duration: Duration,
+    pub frames: Mutex<Vec<AnimationFrame>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExperimentError {
+    #[error("Failed to read experiment file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse TOML experiment: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Failed to write TOML experiment: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("Failed to parse JSON experiment: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Unsupported experiment file extension '{0}', expected .toml or .json")]
+    UnsupportedFormat(String),
+    #[error("Invalid experiment: {0}")]
+    Invalid(String),
+    #[error("Failed to read or write checkpoint: {0}")]
+    Checkpoint(#[from] bincode::Error),
+    #[error(transparent)]
+    RoadState(#[from] RoadStateError),
+    #[error(transparent)]
+    Writer(#[from] WriterError),
+    #[error("Failed to read observations: {0}")]
+    Observations(#[from] csv::Error),
+}
+
+/// A complete description of an experiment, as read from a TOML or JSON file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    /// How often every set of parameters is simulated, the results are averaged together
+    #[serde(default = "default_repetitions")]
+    pub repetitions: usize,
+    /// How many steps are executed within one simulation
+    #[serde(default = "default_iterations")]
+    pub iterations: usize,
+    /// Seed from which all random numbers are derived, a random seed is chosen when absent
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub road: RoadConfig,
+    #[serde(default)]
+    pub probabilities: ProbabilityConfig,
+    pub sweep: SweepConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoadConfig {
+    /// The number of cells in every lane
+    #[serde(default = "default_road_length")]
+    pub length: u8,
+    /// The maximum speed of every lane, from the rightmost lane to the leftmost lane
+    #[serde(default = "default_lane_speeds")]
+    pub lane_speeds: Vec<u8>,
+    /// The density used when the density is not swept
+    #[serde(default = "default_standard_density")]
+    pub standard_density: f32,
+    /// How the vehicles are placed on a new road and how fast they drive
+    #[serde(default)]
+    pub initial_condition: InitialCondition,
+    /// Start every simulation from the road saved in this file instead of a random road.
+    /// The density, lane speeds and length of the road are then taken from the file.
+    pub initial_state: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProbabilityConfig {
+    /// Used when the deceleration probability is not swept
+    #[serde(default = "default_deceleration_probability")]
+    pub deceleration: f32,
+    /// Used when the lane change probability is not swept
+    #[serde(default = "default_lane_change_probability")]
+    pub lane_change: f32,
+}
+
+/// The parameter under test and the values it takes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "parameter", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SweepConfig {
+    Density {
+        start: f32,
+        end: f32,
+        step: f32,
+    },
+    PDecel {
+        start: f32,
+        end: f32,
+        step: f32,
+    },
+    PLaneChange {
+        start: f32,
+        end: f32,
+        step: f32,
+    },
+    Hysteresis {
+        start: f32,
+        end: f32,
+        step: f32,
+    },
+    /// The maximum speed of one lane, from start up to and including end
+    LaneSpeed {
+        lane: usize,
+        start: u8,
+        end: u8,
+    },
+    /// The difference in maximum speed between neighbouring lanes, from start up to and including end
+    SpeedDifferential {
+        start: u8,
+        end: u8,
+    },
+    /// The density from start up to and including end, refined where the flow changes fastest
+    AdaptiveDensity {
+        start: f32,
+        end: f32,
+        budget: usize,
+    },
+    /// Every combination of the given values, parameters that are absent keep their single value
+    Grid {
+        density: Option<GridAxis>,
+        p_decel: Option<GridAxis>,
+        p_lane_change: Option<GridAxis>,
+        lane_speeds: Option<Vec<Vec<u8>>>,
+        road_length: Option<Vec<u8>>,
+    },
+    /// Sobol sensitivity analysis of the flow and average speed,
+    /// parameters with bounds are varied uniformly between them, parameters that are absent keep their single value
+    Sensitivity {
+        /// The number of base samples, the analysis simulates `samples * (varied parameters + 2)` parameter sets
+        samples: usize,
+        density: Option<[f32; 2]>,
+        p_decel: Option<[f32; 2]>,
+        p_lane_change: Option<[f32; 2]>,
+        /// The bounds of the maximum speed of every lane, each lane is varied on its own
+        lane_speed: Option<[u8; 2]>,
+    },
+    /// Search the deceleration and lane change probability and the lane speeds
+    /// that best reproduce the observed fundamental diagram in a csv file with `density`, `flow` and `speed` columns.
+    /// Equal bounds keep a parameter fixed.
+    Calibration {
+        observations: PathBuf,
+        /// The largest number of parameter sets that are simulated
+        #[serde(default = "default_max_evaluations")]
+        max_evaluations: usize,
+        #[serde(default = "default_probability_bounds")]
+        p_decel: [f32; 2],
+        #[serde(default = "default_probability_bounds")]
+        p_lane_change: [f32; 2],
+        #[serde(default = "default_lane_speed_bounds")]
+        lane_speed: [u8; 2],
+    },
+}
+
+/// The values of one parameter in a grid sweep, either listed or as a range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GridAxis {
+    Values(Vec<f32>),
+    Range { start: f32, end: f32, step: f32 },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// The path of the results within the data directory, without an extension
+    pub name: Option<String>,
+    /// Write the results to this file instead of the data directory, the other outputs are written next to it
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub format: ResultFormat,
+    #[serde(default)]
+    pub track_jams: bool,
+    #[serde(default)]
+    pub vehicle_statistics: bool,
+    #[serde(default)]
+    pub gap_histograms: bool,
+    /// Write the number of lane changes in every step to `<name>.lane_changes.csv`
+    #[serde(default)]
+    pub lane_change_steps: bool,
+    /// Steps at the start of every simulation left out of the gap and headway histograms
+    #[serde(default)]
+    pub warmup: usize,
+    /// Record vehicle trajectories in this format
+    pub trajectories: Option<TrajectoryFormat>,
+    /// The first step of which trajectories are recorded
+    #[serde(default)]
+    pub trajectory_start: usize,
+    /// The step from which trajectories are no longer recorded, all steps are recorded when absent
+    pub trajectory_end: Option<usize>,
+    /// The vehicles of which trajectories are recorded, all vehicles are recorded when empty
+    #[serde(default)]
+    pub trajectory_vehicles: Vec<usize>,
+    /// Record every step of one simulation as an animation in this format
+    pub animation: Option<AnimationFormat>,
+    /// The simulation that is animated, starting at 0
+    #[serde(default)]
+    pub animation_simulation: usize,
+    /// The parameter point that is animated, starting at 1, the first point when absent
+    pub animation_iteration: Option<usize>,
+    /// How long every step of the animation is shown in milliseconds, 150 when absent
+    pub animation_frame_ms: Option<u64>,
+    /// Save the state of the road after this step of every simulation
+    pub dump_state_at: Option<usize>,
+    /// The format of the saved road states
+    #[serde(default)]
+    pub state_format: StateFormat,
+}
+
+/// A list of experiments that are run together, as read from a TOML file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchConfig {
+    /// The number of experiments run at the same time, the number of CPUs when absent
+    pub concurrency: Option<usize>,
+    /// Template of the output name of every run that does not have a name,
+    /// placeholders like `{p_lane_change}` or `{l1}` are replaced by the values of the run
+    pub name: Option<String>,
+    /// The experiment every run starts from, in the format of an experiment file
+    #[serde(default)]
+    pub base: toml::Table,
+    /// The changes to the base experiment of every run, in the format of an experiment file
+    pub runs: Vec<toml::Table>,
+}
+
+/// The outcome of one experiment of a batch
+#[derive(Debug, Clone)]
+pub struct BatchRunSummary {
+    pub name: String,
+    /// The number of parameter points written
+    pub points: usize,
+    pub duration: Duration,
+    pub interrupted: bool,
+    pub error: Option<String>,
+}
+
+pub(crate) fn default_repetitions() -> usize {
+    50
+}
+
+pub(crate) fn default_iterations() -> usize {
+    100
+}
+
+pub(crate) fn default_road_length() -> u8 {
+    100
+}
+
+pub(crate) fn default_lane_speeds() -> Vec<u8> {
+    vec![5, 5, 5]
+}
+
+pub(crate) fn default_standard_density() -> f32 {
+    0.3
+}
+
+pub(crate) fn default_deceleration_probability() -> f32 {
+    0.4
+}
+
+pub(crate) fn default_lane_change_probability() -> f32 {
+    0.8
+}
+
+pub(crate) fn default_max_evaluations() -> usize {
+    100
+}
+
+pub(crate) fn default_probability_bounds() -> [f32; 2] {
+    [0.0, 1.0]
+}
+
+pub(crate) fn default_lane_speed_bounds() -> [u8; 2] {
+    [1, 5]
 }
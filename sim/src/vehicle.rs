@@ -1,26 +1,43 @@
-use crate::typedef::{Position, Road, Vehicle, Velocity};
+use crate::rng::{derive_seed, seeded_rng};
+use crate::typedef::{
+    LaneChangeDirection, LaneChangeEvent, Position, Road, Vehicle, VehicleStatistics, Velocity,
+};
 
 use rand::Rng;
 use std::cmp::min;
 
+impl VehicleStatistics {
+    /// The average speed over all steps driven, in cells per step
+    pub fn average_speed(&self) -> f32 {
+        if self.steps == 0 {
+            return 0.0;
+        }
+        self.distance_travelled as f32 / self.steps as f32
+    }
+}
+
 impl Vehicle {
     pub fn new(
+        id: usize,
         position: Position,
         mut vel: Option<Velocity>,
         move_left_chance: f32,
         move_right_chance: f32,
     ) -> Self {
-        if vel == None {
+        if vel.is_none() {
             vel = Some(Velocity::new(0));
         }
         let velocity = vel.unwrap();
 
         Self {
+            id,
             original_lane: position.y,
             position,
             velocity,
             move_left_chance,
             move_right_chance,
+            lane_change_events: Vec::new(),
+            statistics: VehicleStatistics::default(),
         }
     }
 
@@ -53,11 +70,19 @@ impl Vehicle {
     // 3. Distance to previous car on lane+1 is greater that it's speed to avoid emergency braking of previous car.
     // 4. Change lane with probability P.
     pub fn update(self, road: &Road) -> Self {
-        self.update_lane(road).update_x(road)
+        // Every vehicle gets its own random numbers in every step, so the result does not
+        // depend on the order in which the vehicles are updated
+        let mut rng = seeded_rng(derive_seed(road.seed, &[road.steps, self.id as u64]));
+
+        self.update_lane(road, &mut rng)
+            .update_x(road, &mut rng)
+            .update_statistics(road)
     }
 
-    pub fn update_x(self, road: &Road) -> Self {
-        self.accelerate(road).decelerate(road).update_position(road)
+    pub fn update_x(self, road: &Road, rng: &mut impl Rng) -> Self {
+        self.accelerate(road)
+            .decelerate(road, rng)
+            .update_position(road)
     }
 
     fn accelerate(mut self, road: &Road) -> Self {
@@ -68,8 +93,7 @@ impl Vehicle {
         self
     }
 
-    fn decelerate(mut self, road: &Road) -> Self {
-        let mut rng = rand::thread_rng();
+    fn decelerate(mut self, road: &Road, rng: &mut impl Rng) -> Self {
         let r = rng.gen::<f32>();
 
         if r < road.deceleration_probability && self.velocity.into_inner() > 0 {
@@ -80,55 +104,100 @@ impl Vehicle {
     }
 
     fn update_position(mut self, road: &Road) -> Self {
-        self.position.x = (self.position.x + self.velocity.into_inner()) % road.len;
+        // A road of up to 255 cells and a speed of up to 254 do not fit in a u8 together
+        let x = self.position.x as u16 + self.velocity.into_inner() as u16;
+        self.position.x = (x % road.len as u16) as u8;
         self
     }
 
-    //Update the lane of the vehicle
-    fn update_lane(mut self, road: &Road) -> Self {
-        let mut rng = rand::thread_rng();
+    fn update_statistics(mut self, road: &Road) -> Self {
+        let statistics = &mut self.statistics;
+        let speed = self.velocity.into_inner() as usize;
+
+        statistics.steps += 1;
+        statistics.distance_travelled += speed;
+        statistics.laps = statistics.distance_travelled / road.len as usize;
+        if speed == 0 {
+            statistics.time_stopped += 1;
+        }
+        statistics.lane_changes += self
+            .lane_change_events
+            .iter()
+            .filter(|e| matches!(e, LaneChangeEvent::Changed { .. }))
+            .count();
+
+        self
+    }
 
-        if self.willing_to_move_right(road) {
-            if rng.gen_bool(self.move_right_chance as f64) {
-                self.position = self.go_right();
+    //Update the lane of the vehicle
+    //Every lane change made or rejected by the safety check is recorded in `lane_change_events`
+    fn update_lane(mut self, road: &Road, rng: &mut impl Rng) -> Self {
+        let from_lane = self.position.y;
+        self.lane_change_events.clear();
+
+        if self.wants_to_move_right(road) {
+            if self.safe_to_change_lane(road) {
+                if rng.gen_bool(self.move_right_chance as f64) {
+                    self.position = self.go_right();
+                    self.lane_change_events.push(LaneChangeEvent::Changed {
+                        direction: LaneChangeDirection::Right,
+                        from_lane,
+                    });
+                }
+                return self;
             }
-            return self;
+            self.lane_change_events.push(LaneChangeEvent::Unsafe {
+                direction: LaneChangeDirection::Right,
+                from_lane,
+            });
         }
-        if self.willing_to_move_left(road) {
-            if rng.gen_bool(self.move_left_chance as f64) {
-                self.position = self.go_left();
+        if self.wants_to_move_left(road) {
+            if self.safe_to_change_lane(road) {
+                if rng.gen_bool(self.move_left_chance as f64) {
+                    self.position = self.go_left();
+                    self.lane_change_events.push(LaneChangeEvent::Changed {
+                        direction: LaneChangeDirection::Left,
+                        from_lane,
+                    });
+                }
+                return self;
             }
-            return self;
+            self.lane_change_events.push(LaneChangeEvent::Unsafe {
+                direction: LaneChangeDirection::Left,
+                from_lane,
+            });
         }
 
         self
     }
 
-    fn willing_to_move_left(&self, road: &Road) -> bool {
+    fn wants_to_move_left(&self, road: &Road) -> bool {
         if self.can_go_left() {
-            self.willing_to_change_lane(road, self.position.y + 1)
+            self.wants_to_change_lane(road, self.position.y + 1)
         } else {
             false
         }
     }
 
-    fn willing_to_move_right(&self, road: &Road) -> bool {
+    fn wants_to_move_right(&self, road: &Road) -> bool {
         if self.can_go_right() {
-            self.willing_to_change_lane(road, self.position.y - 1)
+            self.wants_to_change_lane(road, self.position.y - 1)
         } else {
             false
         }
     }
 
-    fn willing_to_change_lane(&self, road: &Road, lane: u8) -> bool {
+    /// Check if the vehicle could drive faster in the given lane
+    fn wants_to_change_lane(&self, road: &Road, lane: u8) -> bool {
         let src_lane_speed = road.get_max_velocity_on_position(self.position.clone());
         let dst_lane_speed =
             road.get_max_velocity_on_position(Position::new(self.position.x, lane));
 
-        if dst_lane_speed <= src_lane_speed {
-            return false;
-        }
+        dst_lane_speed > src_lane_speed
+    }
 
+    /// Check if the previous vehicle is far enough away to not have to brake for this vehicle
+    fn safe_to_change_lane(&self, road: &Road) -> bool {
         let pos = &self.position;
         let previous_vehicle = road.find_previous_vehicle(pos.clone());
 